@@ -0,0 +1,260 @@
+use std::{collections::BTreeMap, fmt::Write, path::PathBuf};
+
+use clap::ValueEnum;
+use serde::Serialize;
+use xshell::Shell;
+
+use crate::{
+    consts::LOCALHOST_L2_WEB3, init::InitState, manifest::DeploymentManifest, utils, L1Network,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AddressesFormat {
+    Json,
+    /// `KEY=value` lines, contracts as `CONTRACTS_<NAME>_ADDR`.
+    Env,
+    Toml,
+    Yaml,
+    /// Solidity library with constants, to be imported in Foundry scripts and tests.
+    Foundry,
+    /// L2 network descriptor for MetaMask (`wallet_addEthereumChain`) and chain lists.
+    Metamask,
+}
+
+/// Chain data of the hyperchain shared with the downstream tools.
+#[derive(Debug, Serialize)]
+struct HyperchainAddresses {
+    name: String,
+    l1: L1Chain,
+    l2: L2Chain,
+}
+
+#[derive(Debug, Serialize)]
+struct L1Chain {
+    network: L1Network,
+    chain_id: u64,
+    /// Only exported on request outside of localhost, since it may contain an API key.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rpc_url: Option<String>,
+    /// EIP-55 checksummed addresses of the deployed contracts.
+    contracts: BTreeMap<String, String>,
+}
+
+/// L2 contracts are not deployed by the wizard yet, so only the chain parameters are known.
+#[derive(Debug, Serialize)]
+struct L2Chain {
+    chain_id: u64,
+    rpc_url: String,
+}
+
+/// Parameters of `wallet_addEthereumChain` (EIP-3085).
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct NetworkDescriptor {
+    /// Hex-encoded, as required by the wallets.
+    chain_id: String,
+    chain_name: String,
+    native_currency: NativeCurrency,
+    rpc_urls: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct NativeCurrency {
+    name: &'static str,
+    symbol: &'static str,
+    decimals: u8,
+}
+
+/// Prints the addresses, chain IDs and RPC URLs of the hyperchain in the format expected by
+/// the downstream tools, or writes them to `output`.
+pub fn export(
+    name: &str,
+    format: AddressesFormat,
+    l2_rpc: Option<String>,
+    with_l1_rpc: bool,
+    output: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    let shell = Shell::new()?;
+    let hyperchain_dir = utils::hyperchain_dir(name)?;
+    let state = InitState::load(&shell, &hyperchain_dir)?;
+    let (Some(l1_network), Some(chain_id), Some(web3_rpc)) =
+        (state.l1_network, state.chain_id, state.web3_rpc)
+    else {
+        anyhow::bail!("Hyperchain {name} is not initialized");
+    };
+    let contracts = DeploymentManifest::load(&shell, &hyperchain_dir)?
+        .map(|manifest| manifest.contracts)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(name, contract)| (name, utils::to_checksum(contract.address)))
+        .collect();
+    let addresses = HyperchainAddresses {
+        name: name.to_string(),
+        l1: L1Chain {
+            network: l1_network,
+            chain_id: l1_network.chain_id(),
+            rpc_url: (with_l1_rpc || l1_network == L1Network::Localhost).then_some(web3_rpc),
+            contracts,
+        },
+        l2: L2Chain {
+            chain_id,
+            rpc_url: l2_rpc.unwrap_or_else(|| LOCALHOST_L2_WEB3.to_string()),
+        },
+    };
+
+    let contents = match format {
+        AddressesFormat::Json => serde_json::to_string_pretty(&addresses)? + "\n",
+        AddressesFormat::Env => to_env(&addresses),
+        AddressesFormat::Toml => toml::to_string_pretty(&addresses)?,
+        AddressesFormat::Yaml => serde_yaml::to_string(&addresses)?,
+        AddressesFormat::Foundry => to_solidity(&addresses),
+        AddressesFormat::Metamask => {
+            let descriptor = NetworkDescriptor {
+                chain_id: format!("{:#x}", addresses.l2.chain_id),
+                chain_name: addresses.name,
+                native_currency: NativeCurrency {
+                    name: "Ether",
+                    symbol: "ETH",
+                    decimals: 18,
+                },
+                rpc_urls: vec![addresses.l2.rpc_url],
+            };
+            serde_json::to_string_pretty(&descriptor)? + "\n"
+        }
+    };
+    match output {
+        Some(output) => shell.write_file(output, contents)?,
+        None => print!("{contents}"),
+    }
+    Ok(())
+}
+
+/// Same format as accepted by `init --l1-contracts`.
+fn to_env(addresses: &HyperchainAddresses) -> String {
+    let mut env = format!("L1_CHAIN_ID={}\n", addresses.l1.chain_id);
+    if let Some(rpc_url) = &addresses.l1.rpc_url {
+        writeln!(env, "L1_RPC_URL={rpc_url}").unwrap();
+    }
+    writeln!(
+        env,
+        "L2_CHAIN_ID={}\nL2_RPC_URL={}",
+        addresses.l2.chain_id, addresses.l2.rpc_url
+    )
+    .unwrap();
+    for (name, address) in &addresses.l1.contracts {
+        writeln!(
+            env,
+            "CONTRACTS_{}_ADDR={address}",
+            utils::screaming_snake_case(name)
+        )
+        .unwrap();
+    }
+    env
+}
+
+fn to_solidity(addresses: &HyperchainAddresses) -> String {
+    let mut library = format!(
+        "// SPDX-License-Identifier: MIT\n\
+         pragma solidity ^0.8.0;\n\
+         \n\
+         /// Addresses of hyperchain {}.\n\
+         library HyperchainAddresses {{\n    \
+             uint256 internal constant L1_CHAIN_ID = {};\n",
+        addresses.name, addresses.l1.chain_id
+    );
+    if let Some(rpc_url) = &addresses.l1.rpc_url {
+        writeln!(
+            library,
+            "    string internal constant L1_RPC_URL = \"{rpc_url}\";"
+        )
+        .unwrap();
+    }
+    writeln!(
+        library,
+        "    uint256 internal constant L2_CHAIN_ID = {};\n    \
+         string internal constant L2_RPC_URL = \"{}\";",
+        addresses.l2.chain_id, addresses.l2.rpc_url
+    )
+    .unwrap();
+    for (name, address) in &addresses.l1.contracts {
+        writeln!(
+            library,
+            "    address internal constant {} = {address};",
+            utils::screaming_snake_case(name)
+        )
+        .unwrap();
+    }
+    library.push_str("}\n");
+    library
+}
+
+#[cfg(test)]
+mod tests {
+    use web3::types::Address;
+
+    use super::*;
+    use crate::init::load_contract_addresses;
+
+    fn addresses(rpc_url: Option<&str>) -> HyperchainAddresses {
+        let contracts = [
+            "L1ERC20Bridge",
+            "DiamondProxy",
+            "Multicall3",
+            "Verifier",
+            "MyToken",
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let address = Address::repeat_byte(i as u8 + 1);
+            (name.to_string(), utils::to_checksum(address))
+        })
+        .collect();
+        HyperchainAddresses {
+            name: "hyperchain".to_string(),
+            l1: L1Chain {
+                network: L1Network::Sepolia,
+                chain_id: 11_155_111,
+                rpc_url: rpc_url.map(str::to_string),
+                contracts,
+            },
+            l2: L2Chain {
+                chain_id: 270,
+                rpc_url: LOCALHOST_L2_WEB3.to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn env_names_round_trip() {
+        let addresses = addresses(None);
+        let env = to_env(&addresses);
+        assert!(env.contains("CONTRACTS_L1_ERC20_BRIDGE_ADDR="), "{env}");
+
+        let shell = Shell::new().unwrap();
+        let dir = shell.create_temp_dir().unwrap();
+        let path = dir.path().join("addresses.env");
+        shell.write_file(&path, env).unwrap();
+        let loaded: BTreeMap<_, _> = load_contract_addresses(&shell, &path)
+            .unwrap()
+            .into_iter()
+            .map(|(name, address)| (name, utils::to_checksum(address)))
+            .collect();
+        assert_eq!(loaded, addresses.l1.contracts);
+    }
+
+    #[test]
+    fn l1_rpc_url_is_opt_in() {
+        let env = to_env(&addresses(None));
+        assert!(!env.contains("L1_RPC_URL"), "{env}");
+        let json = serde_json::to_value(addresses(None)).unwrap();
+        assert!(json["l1"].get("rpc_url").is_none());
+        assert!(!to_solidity(&addresses(None)).contains("L1_RPC_URL"));
+
+        let rpc_url = "https://sepolia.example.com/v1/key";
+        let env = to_env(&addresses(Some(rpc_url)));
+        assert!(env.contains(&format!("L1_RPC_URL={rpc_url}\n")), "{env}");
+        let library = to_solidity(&addresses(Some(rpc_url)));
+        assert!(library.contains(&format!("L1_RPC_URL = \"{rpc_url}\";")));
+    }
+}
@@ -0,0 +1,94 @@
+use std::{fs::File, path::Path};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+/// Writer for `.tar.gz` archives used for backups and exports.
+pub struct ArchiveWriter {
+    builder: tar::Builder<GzEncoder<File>>,
+}
+
+impl ArchiveWriter {
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        let file = File::create(path)?;
+        let encoder = GzEncoder::new(file, Compression::default());
+        Ok(Self {
+            builder: tar::Builder::new(encoder),
+        })
+    }
+
+    /// Adds a file with provided contents to the archive.
+    pub fn add_file(&mut self, name: &str, contents: &[u8]) -> anyhow::Result<()> {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        self.builder.append_data(&mut header, name, contents)?;
+        Ok(())
+    }
+
+    /// Recursively adds a directory from the file system to the archive.
+    pub fn add_dir(&mut self, name: &str, path: &Path) -> anyhow::Result<()> {
+        self.builder.append_dir_all(name, path)?;
+        Ok(())
+    }
+
+    pub fn finish(self) -> anyhow::Result<()> {
+        self.builder.into_inner()?.finish()?;
+        Ok(())
+    }
+}
+
+/// Unpacks a `.tar.gz` archive into the provided directory.
+pub fn unpack(archive: &Path, destination: &Path) -> anyhow::Result<()> {
+    let file = File::open(archive)?;
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+    archive.unpack(destination)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use xshell::Shell;
+
+    use super::*;
+
+    #[test]
+    fn archive_round_trip() {
+        let shell = Shell::new().unwrap();
+        let dir = shell.create_temp_dir().unwrap();
+        let source = dir.path().join("source");
+        shell
+            .write_file(source.join("keystore/admin.json"), "{}")
+            .unwrap();
+        shell
+            .write_file(source.join("state.json"), "state")
+            .unwrap();
+        let dump = (0..=255).collect::<Vec<u8>>();
+
+        let path = dir.path().join("backup.tar.gz");
+        let mut archive = ArchiveWriter::create(&path).unwrap();
+        archive.add_file("db.dump", &dump).unwrap();
+        archive.add_dir("hyperchain", &source).unwrap();
+        archive.finish().unwrap();
+
+        let unpacked = dir.path().join("unpacked");
+        shell.create_dir(&unpacked).unwrap();
+        unpack(&path, &unpacked).unwrap();
+        assert_eq!(
+            shell.read_binary_file(unpacked.join("db.dump")).unwrap(),
+            dump
+        );
+        assert_eq!(
+            shell
+                .read_file(unpacked.join("hyperchain/keystore/admin.json"))
+                .unwrap(),
+            "{}"
+        );
+        assert_eq!(
+            shell
+                .read_file(unpacked.join("hyperchain/state.json"))
+                .unwrap(),
+            "state"
+        );
+    }
+}
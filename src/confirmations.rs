@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use xshell::Shell;
+
+use crate::{
+    consts::{CONFIRMATION_CONFIG_FILE, FEE_CONFIG_FILE},
+    utils, L1Network,
+};
+
+/// How deep in the chain the transactions sent to L1 must be before they are considered final.
+/// Stored per L1 network in the workspace, so it can be adjusted by the user.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfirmationConfig {
+    /// Number of blocks to wait for on top of the one with the transaction.
+    pub confirmations: u64,
+}
+
+impl ConfirmationConfig {
+    fn default_for(l1_network: L1Network) -> Self {
+        let confirmations = match l1_network {
+            // Local geth only produces blocks when there are transactions.
+            L1Network::Localhost => 0,
+            L1Network::Sepolia => 3,
+        };
+        Self { confirmations }
+    }
+
+    /// Loads the settings for the network from the workspace, falling back to the defaults.
+    pub fn load(l1_network: L1Network) -> anyhow::Result<Self> {
+        let shell = Shell::new()?;
+        let base_dir = utils::base_dir()?;
+        let path = base_dir.join(CONFIRMATION_CONFIG_FILE);
+        if shell.path_exists(&path) {
+            let mut configs: HashMap<L1Network, Self> =
+                serde_json::from_str(&shell.read_file(path)?)?;
+            return Ok(configs
+                .remove(&l1_network)
+                .unwrap_or_else(|| Self::default_for(l1_network)));
+        }
+
+        // Previous versions kept the confirmations in the fee config, where they may be set.
+        let fee_config_path = base_dir.join(FEE_CONFIG_FILE);
+        if shell.path_exists(&fee_config_path) {
+            let fee_configs: HashMap<L1Network, serde_json::Value> =
+                serde_json::from_str(&shell.read_file(fee_config_path)?)?;
+            let confirmations = fee_configs
+                .get(&l1_network)
+                .and_then(|config| config.get("confirmations"))
+                .and_then(|confirmations| confirmations.as_u64());
+            if let Some(confirmations) = confirmations {
+                return Ok(Self { confirmations });
+            }
+        }
+        Ok(Self::default_for(l1_network))
+    }
+
+    /// Writes the default settings for all the networks, unless the file already exists.
+    pub fn write_defaults(shell: &Shell) -> anyhow::Result<()> {
+        let path = utils::base_dir()?.join(CONFIRMATION_CONFIG_FILE);
+        if shell.path_exists(&path) {
+            return Ok(());
+        }
+        let configs = HashMap::from([
+            (
+                L1Network::Localhost,
+                Self::default_for(L1Network::Localhost),
+            ),
+            (L1Network::Sepolia, Self::default_for(L1Network::Sepolia)),
+        ]);
+        shell.write_file(path, serde_json::to_string_pretty(&configs)?)?;
+        Ok(())
+    }
+}
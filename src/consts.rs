@@ -1,14 +1,41 @@
 use web3::types::H256;
 
-pub(super) const DOCKER_IMAGE: &'static str = "matterlabs/server-v2";
-pub(super) const DOCKER_TAG: &'static str = "bd63b3a-1707838915921";
-pub(super) const GIT_REPO: &'static str = "https://github.com/matter-labs/zksync-era.git";
-pub(super) const GIT_REVISION: &'static str = "bd63b3a";
-pub(super) const APP_NAME: &'static str = "zk_stack_wizard";
+pub(super) const DOCKER_IMAGE: &str = "matterlabs/server-v2";
+pub(super) const DOCKER_TAG: &str = "bd63b3a-1707838915921";
+pub(super) const GIT_REPO: &str = "https://github.com/matter-labs/zksync-era.git";
+pub(super) const GIT_REVISION: &str = "bd63b3a";
+pub(super) const APP_NAME: &str = "zk_stack_wizard";
+pub(super) const PREREQUISITE_CONTRACTS_FILE: &str = ".prerequisite_contracts.json";
+pub(super) const FEE_CONFIG_FILE: &str = ".fee_config.json";
+pub(super) const CONFIRMATION_CONFIG_FILE: &str = ".confirmation_config.json";
+/// JSON-RPC endpoint of the hyperchain server running on this machine.
+pub(super) const LOCALHOST_L2_WEB3: &str = "http://127.0.0.1:3050";
+pub(super) const POSTGRES_CONTAINER: &str = "zkstack_postgres";
+/// L1 contracts of the hyperchains, used to restore their names from `CONTRACTS_<NAME>_ADDR`
+/// keys, which lose the case of acronyms.
+pub(super) const L1_CONTRACT_NAMES: &[&str] = &[
+    "AdminFacet",
+    "AllowList",
+    "DefaultUpgrade",
+    "DiamondInit",
+    "DiamondProxy",
+    "DiamondUpgradeInit",
+    "ExecutorFacet",
+    "GettersFacet",
+    "Governance",
+    "L1ERC20Bridge",
+    "L1ERC20BridgeProxy",
+    "L1WethBridge",
+    "L1WethBridgeProxy",
+    "MailboxFacet",
+    "Multicall3",
+    "SingletonFactory",
+    "ValidatorTimelock",
+    "Verifier",
+];
 
 pub fn localhost_rich_wallet() -> H256 {
     // Only available in localhost geth setup.
-    const RICH_WALLET: &'static str =
-        "7726827caac94a7f9e1b160f7ea819f172f7b6f9d2a97f992c38edeab82d4110";
+    const RICH_WALLET: &str = "7726827caac94a7f9e1b160f7ea819f172f7b6f9d2a97f992c38edeab82d4110";
     RICH_WALLET.parse().unwrap()
 }
@@ -1,58 +1,322 @@
-use std::path::PathBuf;
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    future::Future,
+    path::{Path, PathBuf},
+    pin::Pin,
+};
 
 use anyhow::Context;
+use serde::Deserialize;
+use web3::{
+    ethabi,
+    signing::keccak256,
+    types::{Address, H256},
+};
 use xshell::Shell;
 
-const COMMON_PREFIX_L1: &str = ".contracts/l1-contracts/artifacts/cache/solpp-generated-contracts/";
+use crate::{signer::Signer, web3_client::Web3Client};
 
-#[derive(Debug)]
+const ARTIFACTS_L1: &str = ".contracts/l1-contracts/artifacts/";
+/// Prefix of the L1 contract artifacts, relative to `ARTIFACTS_L1`.
+const COMMON_PREFIX_L1: &str = "cache/solpp-generated-contracts/";
+
+/// Placeholders for library addresses in the bytecode, as reported by Hardhat.
+#[derive(Debug, Clone)]
+pub struct LinkReference {
+    /// Source file of the library.
+    pub file: String,
+    pub library: String,
+    /// Byte offsets of the 20-byte placeholders.
+    pub offsets: Vec<usize>,
+}
+
+impl LinkReference {
+    /// Name that identifies the library among all the libraries with the same name.
+    pub fn qualified_name(&self) -> String {
+        format!("{}:{}", self.file, self.library)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct ContractRepr {
-    // pub abi: web3::ethabi::Contract,
-    pub raw_abi: Vec<u8>,
-    pub bytecode: String,
+    pub name: String,
+    pub abi: ethabi::Contract,
+    /// Creation bytecode, with library placeholders zeroed out.
+    pub bytecode: Vec<u8>,
+    /// Runtime bytecode, if the artifact contains it. Library placeholders are zeroed out.
+    pub deployed_bytecode: Option<Vec<u8>>,
+    pub link_references: Vec<LinkReference>,
+    pub deployed_link_references: Vec<LinkReference>,
 }
 
 impl ContractRepr {
+    /// Parses and validates a Hardhat artifact.
     pub fn new(file: String) -> anyhow::Result<Self> {
-        let raw_abi = file.as_bytes().to_vec();
-        let value: serde_json::Value = serde_json::from_str(&file)?;
+        let value: serde_json::Value = serde_json::from_str(&file).context("Invalid JSON")?;
+        let name = value
+            .get("contractName")
+            .and_then(|name| name.as_str())
+            .unwrap_or("<unnamed>")
+            .to_string();
+        let abi_value = value.get("abi").context("no abi field")?;
+        let abi: ethabi::Contract =
+            serde_json::from_value(abi_value.clone()).context("Invalid ABI")?;
+
+        let link_references =
+            parse_link_references(value.get("linkReferences")).context("Invalid linkReferences")?;
         let bytecode = value
             .get("bytecode")
             .context("no bytecode field")?
             .as_str()
-            .context("bytecode is not a string")?
-            .to_string();
-        let abi_value = value.get("abi").context("no bytecode field")?;
-        let raw_abi = serde_json::to_string(abi_value).unwrap().into_bytes();
-        // let abi: web3::ethabi::Contract =
-        //     serde_json::from_value(value.get("abi").context("no abi field")?.clone())
-        //         .context("Invalid ABI")?;
+            .context("bytecode is not a string")?;
+        let bytecode = decode_bytecode(bytecode, &link_references).context("Invalid bytecode")?;
+
+        let deployed_link_references = parse_link_references(value.get("deployedLinkReferences"))
+            .context("Invalid deployedLinkReferences")?;
+        let deployed_bytecode = match value.get("deployedBytecode") {
+            Some(bytecode) => {
+                let bytecode = bytecode
+                    .as_str()
+                    .context("deployedBytecode is not a string")?;
+                let bytecode = decode_bytecode(bytecode, &deployed_link_references)
+                    .context("Invalid deployedBytecode")?;
+                Some(bytecode)
+            }
+            None => None,
+        };
+
         Ok(Self {
+            name,
+            abi,
             bytecode,
-            // abi,
-            raw_abi,
+            deployed_bytecode,
+            link_references,
+            deployed_link_references,
         })
     }
+
+    /// Fills the library placeholders with the addresses of the deployed libraries,
+    /// keyed by `<file>:<library>`.
+    pub fn link(&self, libraries: &BTreeMap<String, Address>) -> anyhow::Result<Self> {
+        let mut linked = self.clone();
+        for (bytecode, references) in [
+            (Some(&mut linked.bytecode), &self.link_references),
+            (
+                linked.deployed_bytecode.as_mut(),
+                &self.deployed_link_references,
+            ),
+        ] {
+            let Some(bytecode) = bytecode else {
+                continue;
+            };
+            for reference in references {
+                let address = libraries
+                    .get(&reference.qualified_name())
+                    .with_context(|| format!("Library {} is not deployed", reference.library))?;
+                for offset in &reference.offsets {
+                    bytecode[*offset..*offset + 20].copy_from_slice(address.as_bytes());
+                }
+            }
+        }
+        linked.link_references.clear();
+        linked.deployed_link_references.clear();
+        Ok(linked)
+    }
+
+    /// Runtime bytecode expected at the address the contract is deployed to.
+    pub fn expected_code(&self, address: Address) -> Option<Vec<u8>> {
+        if !self.deployed_link_references.is_empty() {
+            return None;
+        }
+        let mut code = self.deployed_bytecode.clone()?;
+        // Libraries start with `PUSH20 <address> ADDRESS EQ` to protect against direct calls.
+        // The address is only filled in during the deployment.
+        let is_library = code.len() > 22 && code[0] == 0x73 && code[21..23] == [0x30, 0x14];
+        if is_library && code[1..21].iter().all(|byte| *byte == 0) {
+            code[1..21].copy_from_slice(address.as_bytes());
+        }
+        Some(code)
+    }
+
+    /// Whether the runtime code deployed by other tooling matches the artifact, whichever
+    /// libraries it's linked with. `None` if the artifact doesn't contain the runtime code.
+    pub fn matches_deployed_code(&self, code: &[u8]) -> Option<bool> {
+        let expected = self.deployed_bytecode.as_ref()?;
+        if code.len() != expected.len() {
+            return Some(false);
+        }
+        let mut code = code.to_vec();
+        for reference in &self.deployed_link_references {
+            for offset in &reference.offsets {
+                code[*offset..*offset + 20].fill(0);
+            }
+        }
+        Some(code == *expected)
+    }
+
+    /// Hash of the runtime bytecode. `None` if the artifact doesn't contain it, or it contains
+    /// placeholders for libraries that are only known after linking.
+    pub fn bytecode_hash(&self) -> Option<H256> {
+        if !self.deployed_link_references.is_empty() {
+            return None;
+        }
+        let bytecode = self.deployed_bytecode.as_ref()?;
+        Some(H256(keccak256(bytecode)))
+    }
+}
+
+fn parse_link_references(value: Option<&serde_json::Value>) -> anyhow::Result<Vec<LinkReference>> {
+    #[derive(Deserialize)]
+    struct Offset {
+        start: usize,
+        length: usize,
+    }
+
+    let Some(value) = value else {
+        return Ok(Vec::new());
+    };
+    let files: BTreeMap<String, BTreeMap<String, Vec<Offset>>> =
+        serde_json::from_value(value.clone())?;
+    let mut references = Vec::new();
+    for (file, libraries) in files {
+        for (library, offsets) in libraries {
+            if let Some(offset) = offsets.iter().find(|offset| offset.length != 20) {
+                anyhow::bail!(
+                    "Placeholder for {library} at {} has length {}",
+                    offset.start,
+                    offset.length
+                );
+            }
+            references.push(LinkReference {
+                file: file.clone(),
+                library,
+                offsets: offsets.into_iter().map(|offset| offset.start).collect(),
+            });
+        }
+    }
+    Ok(references)
+}
+
+/// Decodes the hex bytecode, replacing the library placeholders (`__$...$__`) with zeroes.
+fn decode_bytecode(bytecode: &str, link_references: &[LinkReference]) -> anyhow::Result<Vec<u8>> {
+    let mut bytecode = bytecode.trim_start_matches("0x").to_string();
+    for reference in link_references {
+        for offset in &reference.offsets {
+            let range = offset * 2..offset * 2 + 40;
+            let placeholder = bytecode.get(range.clone()).with_context(|| {
+                format!(
+                    "Placeholder for {} at {offset} is out of bounds",
+                    reference.library
+                )
+            })?;
+            if !placeholder.starts_with("__") {
+                anyhow::bail!("No placeholder for {} at {offset}", reference.library);
+            }
+            bytecode.replace_range(range, &"0".repeat(40));
+        }
+    }
+    if bytecode.contains("__") {
+        anyhow::bail!("Bytecode contains library placeholders missing from link references");
+    }
+    Ok(hex::decode(bytecode)?)
 }
 
+/// Hardhat artifacts of the L1 contracts, keyed by the path relative to the artifacts folder.
 #[derive(Debug)]
-pub struct Contracts<'a> {
-    shell: &'a Shell,
-    base_folder: PathBuf,
+pub struct Contracts {
+    artifacts: BTreeMap<PathBuf, ContractRepr>,
 }
 
-impl<'a> Contracts<'a> {
-    pub fn new(shell: &'a Shell, base_folder: PathBuf) -> Self {
-        Self { shell, base_folder }
+impl Contracts {
+    /// Loads and validates all the L1 artifacts, so that a broken checkout is reported
+    /// before anything is deployed.
+    pub fn load(shell: &Shell, base_folder: &Path) -> anyhow::Result<Self> {
+        let root = base_folder.join(ARTIFACTS_L1);
+        if !shell.path_exists(&root) {
+            anyhow::bail!("No such path: {:?}", root);
+        }
+        let mut artifacts = BTreeMap::new();
+        let mut invalid = Vec::new();
+        let mut dirs = vec![root.clone()];
+        while let Some(dir) = dirs.pop() {
+            for path in shell.read_dir(&dir)? {
+                if path.is_dir() {
+                    // Build info contains the compiler input and output, not artifacts.
+                    if path.file_name() != Some(OsStr::new("build-info")) {
+                        dirs.push(path);
+                    }
+                    continue;
+                }
+                let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+                if !file_name.ends_with(".json") || file_name.ends_with(".dbg.json") {
+                    continue;
+                }
+                let relative_path = path.strip_prefix(&root)?.to_path_buf();
+                match ContractRepr::new(shell.read_file(&path)?) {
+                    Ok(contract) => {
+                        artifacts.insert(relative_path, contract);
+                    }
+                    Err(err) => invalid.push(format!("{}: {err:#}", relative_path.display())),
+                }
+            }
+        }
+        if !invalid.is_empty() {
+            anyhow::bail!("Invalid contract artifacts:\n{}", invalid.join("\n"));
+        }
+        Ok(Self { artifacts })
+    }
+
+    fn artifact(&self, relative_path: PathBuf) -> anyhow::Result<ContractRepr> {
+        self.artifacts
+            .get(&relative_path)
+            .cloned()
+            .with_context(|| format!("No contract artifact {:?}", relative_path))
     }
 
     fn load_l1(&self, relative_path: &str) -> anyhow::Result<ContractRepr> {
-        let path = self.base_folder.join(COMMON_PREFIX_L1).join(relative_path);
-        if !self.shell.path_exists(&path) {
-            anyhow::bail!("No such path: {:?}", path);
-        }
-        let file = self.shell.read_file(&path)?;
-        ContractRepr::new(file)
+        self.artifact(Path::new(COMMON_PREFIX_L1).join(relative_path))
+    }
+
+    /// Loads the artifact of the library referenced by an L1 contract.
+    fn load_l1_library(&self, reference: &LinkReference) -> anyhow::Result<ContractRepr> {
+        self.artifact(Path::new(&reference.file).join(format!("{}.json", reference.library)))
+    }
+
+    /// Deploys the libraries the contract references (and the libraries they reference),
+    /// unless they are already in `libraries`, and returns the linked contract.
+    /// Addresses of the deployed libraries are added to `libraries`.
+    pub fn link<'b>(
+        &'b self,
+        client: &'b Web3Client,
+        signer: &'b dyn Signer,
+        contract: &'b ContractRepr,
+        libraries: &'b mut BTreeMap<String, Address>,
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<ContractRepr>> + 'b>> {
+        Box::pin(async move {
+            let references = contract
+                .link_references
+                .iter()
+                .chain(&contract.deployed_link_references);
+            for reference in references {
+                let name = reference.qualified_name();
+                if libraries.contains_key(&name) {
+                    continue;
+                }
+                let library = self.load_l1_library(reference)?;
+                let library = self.link(client, signer, &library, libraries).await?;
+                let deployed = client
+                    .deploy(signer, &reference.library, &library, ())
+                    .await?;
+                println!(
+                    "Deployed library {} to {:?}",
+                    reference.library,
+                    deployed.address()
+                );
+                libraries.insert(name, deployed.address());
+            }
+            contract.link(libraries)
+        })
     }
 
     pub fn create2_factory(&self) -> anyhow::Result<ContractRepr> {
@@ -64,6 +328,78 @@ impl<'a> Contracts<'a> {
     }
 
     pub fn verifier(&self) -> anyhow::Result<ContractRepr> {
-        todo!()
+        self.load_l1("zksync/Verifier.sol/Verifier.json")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLACEHOLDER: &str = "__$0123456789abcdef0123456789abcdef01$__";
+    const LIBRARY_FILE: &str = "cache/solpp-generated-contracts/zksync/libraries/Lib.sol";
+
+    /// Artifact of a contract referencing `Lib` twice in the creation code and once
+    /// in the runtime code.
+    fn artifact() -> ContractRepr {
+        let artifact = serde_json::json!({
+            "contractName": "Linked",
+            "abi": [],
+            "bytecode": format!("0x6001{PLACEHOLDER}6002{PLACEHOLDER}00"),
+            "deployedBytecode": format!("0x60ff{PLACEHOLDER}00"),
+            "linkReferences": {
+                LIBRARY_FILE: { "Lib": [{ "start": 2, "length": 20 }, { "start": 24, "length": 20 }] }
+            },
+            "deployedLinkReferences": {
+                LIBRARY_FILE: { "Lib": [{ "start": 2, "length": 20 }] }
+            }
+        });
+        ContractRepr::new(artifact.to_string()).unwrap()
+    }
+
+    #[test]
+    fn placeholders_are_replaced_at_every_offset() {
+        let contract = artifact();
+        assert_eq!(contract.bytecode_hash(), None);
+        let library = Address::repeat_byte(0xab);
+        let libraries = BTreeMap::from([(format!("{LIBRARY_FILE}:Lib"), library)]);
+
+        let linked = contract.link(&libraries).unwrap();
+        let expected = [
+            &[0x60, 0x01][..],
+            library.as_bytes(),
+            &[0x60, 0x02],
+            library.as_bytes(),
+            &[0x00],
+        ]
+        .concat();
+        assert_eq!(linked.bytecode, expected);
+        let expected_deployed = [&[0x60, 0xff][..], library.as_bytes(), &[0x00]].concat();
+        assert_eq!(linked.deployed_bytecode, Some(expected_deployed.clone()));
+        assert!(linked.link_references.is_empty());
+        assert!(linked.deployed_link_references.is_empty());
+        assert_eq!(
+            linked.bytecode_hash(),
+            Some(H256(keccak256(&expected_deployed)))
+        );
+    }
+
+    #[test]
+    fn unresolved_library_is_an_error() {
+        let contract = artifact();
+        let other = BTreeMap::from([(format!("{LIBRARY_FILE}:Other"), Address::repeat_byte(1))]);
+        for libraries in [BTreeMap::new(), other] {
+            let err = contract.link(&libraries).unwrap_err();
+            assert_eq!(err.to_string(), "Library Lib is not deployed");
+        }
+    }
+    #[test]
+    fn deployed_code_is_matched_with_any_libraries() {
+        let contract = artifact();
+        let code = [&[0x60, 0xff][..], &[0xcd; 20], &[0x00]].concat();
+        assert_eq!(contract.matches_deployed_code(&code), Some(true));
+        let other_code = [&[0x60, 0xfe][..], &[0xcd; 20], &[0x00]].concat();
+        assert_eq!(contract.matches_deployed_code(&other_code), Some(false));
+        assert_eq!(contract.matches_deployed_code(&code[..22]), Some(false));
     }
 }
@@ -0,0 +1,261 @@
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::Context;
+use sqlx::{Connection, PgConnection};
+use xshell::{cmd, Shell};
+
+use crate::{
+    archive::{self, ArchiveWriter},
+    consts::POSTGRES_CONTAINER,
+    init::{self, create_db, InitState, BASE_DB_URL, STATE_FILE_NAME},
+    utils, L1Network,
+};
+
+pub(crate) const DB_DUMP_FILE_NAME: &str = "db.dump";
+pub(crate) const PROVER_DB_DUMP_FILE_NAME: &str = "prover_db.dump";
+
+/// Dumps the hyperchain databases together with its state file into an archive.
+pub async fn backup(name: &str, output: Option<PathBuf>) -> anyhow::Result<()> {
+    let shell = Shell::new()?;
+    let hyperchain_dir = utils::hyperchain_dir(name)?;
+    let state = InitState::load(&shell, &hyperchain_dir)?;
+    let Some(db_name) = &state.db_name else {
+        anyhow::bail!("Hyperchain {name} doesn't have an initialized database");
+    };
+
+    let output = match output {
+        Some(output) => output,
+        None => {
+            let timestamp = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)?
+                .as_secs();
+            PathBuf::from(format!("{name}-{timestamp}.tar.gz"))
+        }
+    };
+
+    let mut archive = ArchiveWriter::create(&output)?;
+    archive.add_file(
+        STATE_FILE_NAME,
+        &shell.read_binary_file(hyperchain_dir.join(STATE_FILE_NAME))?,
+    )?;
+    println!("Dumping database {db_name}");
+    archive.add_file(DB_DUMP_FILE_NAME, &dump_db(&shell, db_name)?)?;
+    if let Some(prover_db_name) = &state.prover_db_name {
+        println!("Dumping database {prover_db_name}");
+        archive.add_file(PROVER_DB_DUMP_FILE_NAME, &dump_db(&shell, prover_db_name)?)?;
+    }
+    archive.finish()?;
+
+    println!("Backup saved to {}", output.display());
+    Ok(())
+}
+
+/// Restores the hyperchain databases from an archive created by `backup`.
+///
+/// The databases are restored under the names of hyperchain `name`, so a backup of another
+/// hyperchain never overwrites the databases it still uses. Only the database part of the
+/// state is taken from the backup, see `merge_backup_state`.
+pub async fn restore(name: &str, file: &Path) -> anyhow::Result<()> {
+    let shell = Shell::new()?;
+    let hyperchain_dir = utils::hyperchain_dir(name)?;
+
+    let temp_dir = shell.create_temp_dir()?;
+    archive::unpack(file, temp_dir.path())?;
+    if !shell.path_exists(temp_dir.path().join(STATE_FILE_NAME)) {
+        anyhow::bail!("Backup doesn't contain the hyperchain state file");
+    }
+    let backup_state = InitState::load(&shell, temp_dir.path())?;
+    if backup_state.db_name.is_none() {
+        anyhow::bail!("Backup doesn't contain a database");
+    }
+
+    let mut state = InitState::load(&shell, &hyperchain_dir)?;
+    let Some(l1_network) = state.l1_network.or(backup_state.l1_network) else {
+        anyhow::bail!("Neither hyperchain {name} nor the backup have the L1 network recorded");
+    };
+    if backup_state
+        .l1_network
+        .is_some_and(|network| network != l1_network)
+    {
+        anyhow::bail!("Backup is made for another L1 network than hyperchain {name} uses");
+    }
+
+    // Database names are derived from the hyperchain name, so they have to be rewired.
+    let db_name = state
+        .db_name
+        .clone()
+        .unwrap_or_else(|| init::db_name(name, l1_network));
+    println!("Restoring database {db_name}");
+    let dump = shell.read_binary_file(temp_dir.path().join(DB_DUMP_FILE_NAME))?;
+    restore_db(&shell, &db_name, &dump).await?;
+    let prover_db_name = match backup_state.prover_db_name {
+        Some(_) => {
+            let prover_db_name = state
+                .prover_db_name
+                .clone()
+                .unwrap_or_else(|| init::prover_db_name(name, l1_network));
+            println!("Restoring database {prover_db_name}");
+            let dump = shell.read_binary_file(temp_dir.path().join(PROVER_DB_DUMP_FILE_NAME))?;
+            restore_db(&shell, &prover_db_name, &dump).await?;
+            Some(prover_db_name)
+        }
+        None => None,
+    };
+
+    merge_backup_state(
+        &mut state,
+        &backup_state,
+        l1_network,
+        db_name,
+        prover_db_name,
+    );
+    shell.create_dir(&hyperchain_dir)?;
+    state.save(&shell, &hyperchain_dir)?;
+    init::write_db_config(&shell, &hyperchain_dir, &state)?;
+    println!("Hyperchain {name} restored from {}", file.display());
+    Ok(())
+}
+
+/// Takes the restored databases into the hyperchain state. The rest of the state is kept,
+/// e.g. the wallets, since their keystores are not in the backup. The prover database is
+/// only replaced if the backup has one.
+fn merge_backup_state(
+    state: &mut InitState,
+    backup_state: &InitState,
+    l1_network: L1Network,
+    db_name: String,
+    prover_db_name: Option<String>,
+) {
+    state.l1_network = Some(l1_network);
+    state.chain_id = state.chain_id.or(backup_state.chain_id);
+    state.db_name = Some(db_name);
+    state.migrations_applied = backup_state.migrations_applied;
+    if let Some(prover_db_name) = prover_db_name {
+        state.prover_db_name = Some(prover_db_name);
+        state.prover_migrations_applied = backup_state.prover_migrations_applied;
+    }
+}
+
+/// Dumps the database in the custom `pg_dump` format.
+/// `pg_dump` is invoked inside of the postgres container, so it's not required on the host.
+pub(crate) fn dump_db(shell: &Shell, db_name: &str) -> anyhow::Result<Vec<u8>> {
+    let output = cmd!(
+        shell,
+        "docker exec {POSTGRES_CONTAINER} pg_dump -U postgres -Fc {db_name}"
+    )
+    .quiet()
+    .output()?;
+    Ok(output.stdout)
+}
+
+/// Recreates the database and fills it with the dump created by `dump_db`.
+///
+/// The dump is restored into a staging database first, which replaces the existing database
+/// only once it's fully restored. A failed restore leaves the existing database intact.
+pub(crate) async fn restore_db(shell: &Shell, db_name: &str, dump: &[u8]) -> anyhow::Result<()> {
+    let staging_db_name = format!("{db_name}_restoring");
+    drop_db(&staging_db_name).await?;
+    create_db(&staging_db_name).await?;
+    let restored = cmd!(
+        shell,
+        "docker exec -i {POSTGRES_CONTAINER} pg_restore -U postgres --no-owner --exit-on-error -d {staging_db_name}"
+    )
+    .stdin(dump)
+    .quiet()
+    .run();
+    if let Err(err) = restored {
+        drop_db(&staging_db_name).await?;
+        return Err(err).with_context(|| format!("Unable to restore database {db_name}"));
+    }
+
+    drop_db(db_name).await?;
+    let mut connection = PgConnection::connect(BASE_DB_URL).await?;
+    let query = format!("ALTER DATABASE {staging_db_name} RENAME TO {db_name}");
+    sqlx::query(&query).execute(&mut connection).await?;
+    Ok(())
+}
+
+pub(crate) async fn db_exists(db_name: &str) -> anyhow::Result<bool> {
+    let mut connection = PgConnection::connect(BASE_DB_URL).await?;
+    let exists: bool =
+        sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM pg_database WHERE datname = $1)")
+            .bind(db_name)
+            .fetch_one(&mut connection)
+            .await?;
+    Ok(exists)
+}
+
+pub(crate) async fn drop_db(db_name: &str) -> anyhow::Result<()> {
+    let mut connection = PgConnection::connect(BASE_DB_URL).await?;
+    // `FORCE` terminates any connections to the database that are still open.
+    let query = format!("DROP DATABASE IF EXISTS {db_name} WITH (FORCE)");
+    sqlx::query(&query).execute(&mut connection).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use web3::types::Address;
+
+    use super::*;
+    use crate::wallets::WalletRole;
+
+    #[test]
+    fn backup_only_replaces_databases() {
+        let mut state = InitState::default();
+        state.l1_network = Some(L1Network::Sepolia);
+        state.chain_id = Some(270);
+        state.db_name = Some("target_sepolia".to_string());
+        state.prover_db_name = Some("target_sepolia_prover".to_string());
+        state.prover_migrations_applied = true;
+        state.wallets = BTreeMap::from([(WalletRole::Admin, Address::repeat_byte(1))]);
+        state.wallets_funded = true;
+        let mut backup_state = InitState::default();
+        backup_state.l1_network = Some(L1Network::Sepolia);
+        backup_state.chain_id = Some(271);
+        backup_state.db_name = Some("source_sepolia".to_string());
+        backup_state.migrations_applied = true;
+        backup_state.wallets = BTreeMap::from([(WalletRole::Admin, Address::repeat_byte(2))]);
+
+        merge_backup_state(
+            &mut state,
+            &backup_state,
+            L1Network::Sepolia,
+            "target_sepolia".to_string(),
+            None,
+        );
+        assert_eq!(state.db_name.as_deref(), Some("target_sepolia"));
+        assert!(state.migrations_applied);
+        // The backup has no prover database, so the existing one is kept.
+        assert_eq!(
+            state.prover_db_name.as_deref(),
+            Some("target_sepolia_prover")
+        );
+        assert!(state.prover_migrations_applied);
+        assert_eq!(state.chain_id, Some(270));
+        assert_eq!(state.wallets[&WalletRole::Admin], Address::repeat_byte(1));
+        assert!(state.wallets_funded);
+
+        let mut new_state = InitState::default();
+        merge_backup_state(
+            &mut new_state,
+            &backup_state,
+            L1Network::Sepolia,
+            "new_sepolia".to_string(),
+            Some("new_sepolia_prover".to_string()),
+        );
+        assert_eq!(new_state.l1_network, Some(L1Network::Sepolia));
+        assert_eq!(new_state.chain_id, Some(271));
+        assert_eq!(
+            new_state.prover_db_name.as_deref(),
+            Some("new_sepolia_prover")
+        );
+        assert!(!new_state.prover_migrations_applied);
+        assert!(new_state.wallets.is_empty());
+    }
+}
@@ -0,0 +1,280 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use xshell::{cmd, Shell};
+
+use crate::{
+    archive::{self, ArchiveWriter},
+    consts::PREREQUISITE_CONTRACTS_FILE,
+    db::{self, DB_DUMP_FILE_NAME, PROVER_DB_DUMP_FILE_NAME},
+    init::{self, InitState},
+    utils, L1Network, PrerequisiteContracts,
+};
+
+const METADATA_FILE_NAME: &str = "export.json";
+const HYPERCHAIN_DIR_NAME: &str = "hyperchain";
+const GETH_VOLUME_DIR_NAME: &str = "volumes/geth";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ExportMetadata {
+    /// Name of the exported hyperchain.
+    name: String,
+    /// Whether the archive contains the L1 state (only for localhost).
+    with_l1: bool,
+}
+
+/// Packs everything required to recreate the hyperchain on another machine into an archive.
+pub async fn export(name: &str, output: Option<PathBuf>) -> anyhow::Result<()> {
+    let shell = Shell::new()?;
+    let base_dir = utils::base_dir()?;
+    let hyperchain_dir = utils::hyperchain_dir(name)?;
+    let state = InitState::load(&shell, &hyperchain_dir)?;
+    let Some(db_name) = &state.db_name else {
+        anyhow::bail!("Hyperchain {name} is not initialized");
+    };
+    // Hyperchains created by the older versions don't have these recorded, while `import`
+    // can't check collisions without them. `init` records them once it's run again.
+    let (Some(l1_network), Some(_)) = (state.l1_network, state.chain_id) else {
+        anyhow::bail!(
+            "Hyperchain {name} doesn't have its L1 network and chain ID recorded, run `init` for it again before exporting"
+        );
+    };
+    let output = output.unwrap_or_else(|| PathBuf::from(format!("{name}.tar.gz")));
+
+    let mut archive = ArchiveWriter::create(&output)?;
+    archive.add_dir(HYPERCHAIN_DIR_NAME, &hyperchain_dir)?;
+    println!("Dumping database {db_name}");
+    archive.add_file(DB_DUMP_FILE_NAME, &db::dump_db(&shell, db_name)?)?;
+    if let Some(prover_db_name) = &state.prover_db_name {
+        println!("Dumping database {prover_db_name}");
+        archive.add_file(
+            PROVER_DB_DUMP_FILE_NAME,
+            &db::dump_db(&shell, prover_db_name)?,
+        )?;
+    }
+
+    let with_l1 = l1_network == L1Network::Localhost;
+    if with_l1 {
+        // Geth must be stopped so that we get a consistent snapshot of its data.
+        println!("Copying localhost L1 state");
+        shell.change_dir(&base_dir);
+        cmd!(shell, "docker-compose stop zkstack_geth").run()?;
+        let result = archive.add_dir(GETH_VOLUME_DIR_NAME, &base_dir.join(GETH_VOLUME_DIR_NAME));
+        crate::start_containers(&shell)?;
+        result?;
+        archive.add_file(
+            PREREQUISITE_CONTRACTS_FILE,
+            &shell.read_binary_file(base_dir.join(PREREQUISITE_CONTRACTS_FILE))?,
+        )?;
+    }
+
+    let metadata = ExportMetadata {
+        name: name.to_string(),
+        with_l1,
+    };
+    archive.add_file(
+        METADATA_FILE_NAME,
+        serde_json::to_string_pretty(&metadata)?.as_bytes(),
+    )?;
+    archive.finish()?;
+
+    println!("Hyperchain {name} exported to {}", output.display());
+    Ok(())
+}
+
+/// Recreates a hyperchain from an archive created by `export` in the current workspace.
+pub async fn import(archive: &Path, name: Option<String>) -> anyhow::Result<()> {
+    let shell = Shell::new()?;
+    let base_dir = utils::base_dir()?;
+
+    let temp_dir = shell.create_temp_dir()?;
+    let unpacked = temp_dir.path();
+    archive::unpack(archive, unpacked)?;
+    if !shell.path_exists(unpacked.join(METADATA_FILE_NAME)) {
+        anyhow::bail!("{} is not a hyperchain export", archive.display());
+    }
+    let metadata: ExportMetadata =
+        serde_json::from_str(&shell.read_file(unpacked.join(METADATA_FILE_NAME))?)?;
+    let name = name.unwrap_or(metadata.name);
+
+    let hyperchain_dir = utils::hyperchain_dir(&name)?;
+    if shell.path_exists(&hyperchain_dir) {
+        anyhow::bail!("Hyperchain {name} already exists, use another name to import it");
+    }
+
+    let mut state = InitState::load(&shell, &unpacked.join(HYPERCHAIN_DIR_NAME))?;
+    let (Some(l1_network), Some(chain_id)) = (state.l1_network, state.chain_id) else {
+        anyhow::bail!("Exported hyperchain doesn't have network parameters recorded");
+    };
+    let existing = utils::hyperchains(&shell)?;
+    for other in &existing {
+        let other_state = InitState::load(&shell, &utils::hyperchain_dir(other)?)?;
+        if recorded_l1_network(&other_state) != Some(l1_network) {
+            continue;
+        }
+        match other_state.chain_id {
+            Some(other_chain_id) if other_chain_id == chain_id => {
+                anyhow::bail!("Hyperchain {other} already uses chain ID {chain_id} on {l1_network}");
+            }
+            Some(_) => {}
+            None => anyhow::bail!(
+                "Hyperchain {other} doesn't have its chain ID recorded, run `init` for it again so that collisions can be checked"
+            ),
+        }
+    }
+    if metadata.with_l1 {
+        // Localhost L1 is shared between all the localhost hyperchains in the workspace,
+        // so we can only replace it if there are no other hyperchains relying on it.
+        for other in &existing {
+            let other_state = InitState::load(&shell, &utils::hyperchain_dir(other)?)?;
+            if recorded_l1_network(&other_state) == Some(L1Network::Localhost) {
+                anyhow::bail!(
+                    "Cannot import localhost L1 state: hyperchain {other} uses localhost L1 in this workspace"
+                );
+            }
+        }
+    }
+
+    // Database names are derived from the hyperchain name, so they have to be rewired.
+    let dumps = [
+        (
+            &mut state.db_name,
+            init::db_name(&name, l1_network),
+            DB_DUMP_FILE_NAME,
+        ),
+        (
+            &mut state.prover_db_name,
+            init::prover_db_name(&name, l1_network),
+            PROVER_DB_DUMP_FILE_NAME,
+        ),
+    ];
+    let mut restored = Vec::new();
+    for (db_name, new_db_name, dump_file) in dumps {
+        if db_name.is_none() {
+            continue;
+        }
+        if db::db_exists(&new_db_name).await? {
+            anyhow::bail!("Database {new_db_name} already exists");
+        }
+        if !shell.path_exists(unpacked.join(dump_file)) {
+            anyhow::bail!("Export doesn't contain the dump of database {new_db_name}");
+        }
+        *db_name = Some(new_db_name.clone());
+        restored.push((new_db_name, dump_file));
+    }
+    if metadata.with_l1 && !shell.path_exists(unpacked.join(GETH_VOLUME_DIR_NAME)) {
+        anyhow::bail!("Export doesn't contain the localhost L1 state");
+    }
+
+    // The shared L1 state is replaced last, so that a failure before leaves the workspace
+    // intact, and the hyperchain is rolled back if replacing it fails.
+    let imported = import_hyperchain(&shell, unpacked, &hyperchain_dir, &state, &restored).await;
+    let imported = match imported {
+        Ok(()) if metadata.with_l1 => import_l1_state(&shell, &base_dir, unpacked),
+        imported => imported,
+    };
+    if let Err(err) = imported {
+        println!("Import failed, removing hyperchain {name}");
+        for (db_name, _) in &restored {
+            db::drop_db(db_name).await?;
+        }
+        shell.remove_path(&hyperchain_dir)?;
+        return Err(err);
+    }
+
+    println!("Hyperchain {name} imported from {}", archive.display());
+    Ok(())
+}
+
+/// Restores the databases of the imported hyperchain and creates its directory.
+async fn import_hyperchain(
+    shell: &Shell,
+    unpacked: &Path,
+    hyperchain_dir: &Path,
+    state: &InitState,
+    restored: &[(String, &str)],
+) -> anyhow::Result<()> {
+    for (db_name, dump_file) in restored {
+        println!("Restoring database {db_name}");
+        let dump = shell.read_binary_file(unpacked.join(dump_file))?;
+        db::restore_db(shell, db_name, &dump).await?;
+    }
+    utils::copy_dir_all(shell, &unpacked.join(HYPERCHAIN_DIR_NAME), hyperchain_dir)?;
+    state.save(shell, hyperchain_dir)?;
+    init::write_db_config(shell, hyperchain_dir, state)?;
+    Ok(())
+}
+
+/// L1 network of the hyperchain. Hyperchains created by the older versions don't have it
+/// recorded, but their database name ends with it.
+fn recorded_l1_network(state: &InitState) -> Option<L1Network> {
+    state.l1_network.or_else(|| {
+        let db_name = state.db_name.as_ref()?;
+        [L1Network::Localhost, L1Network::Sepolia]
+            .into_iter()
+            .find(|network| db_name.ends_with(&format!("_{network}").to_lowercase()))
+    })
+}
+
+fn import_l1_state(shell: &Shell, base_dir: &Path, unpacked: &Path) -> anyhow::Result<()> {
+    println!("Replacing localhost L1 state");
+    let geth_volume = base_dir.join(GETH_VOLUME_DIR_NAME);
+    let staged_volume = geth_volume.with_extension("imported");
+    let previous_volume = geth_volume.with_extension("previous");
+    shell.remove_path(&staged_volume)?;
+    utils::copy_dir_all(shell, &unpacked.join(GETH_VOLUME_DIR_NAME), &staged_volume)?;
+
+    shell.change_dir(base_dir);
+    cmd!(shell, "docker-compose stop zkstack_geth").run()?;
+    shell.remove_path(&previous_volume)?;
+    std::fs::rename(&geth_volume, &previous_volume)?;
+    if let Err(err) = std::fs::rename(&staged_volume, &geth_volume) {
+        std::fs::rename(&previous_volume, &geth_volume)?;
+        crate::start_containers(shell)?;
+        return Err(err.into());
+    }
+    shell.remove_path(&previous_volume)?;
+    crate::start_containers(shell)?;
+
+    // Prerequisite contracts were deployed to the imported L1, so their addresses change as well.
+    let contracts_file = base_dir.join(PREREQUISITE_CONTRACTS_FILE);
+    let mut contracts: HashMap<L1Network, PrerequisiteContracts> =
+        serde_json::from_str(&shell.read_file(&contracts_file)?)?;
+    let mut imported_contracts: HashMap<L1Network, PrerequisiteContracts> =
+        serde_json::from_str(&shell.read_file(unpacked.join(PREREQUISITE_CONTRACTS_FILE))?)?;
+    if let Some(localhost) = imported_contracts.remove(&L1Network::Localhost) {
+        contracts.insert(L1Network::Localhost, localhost);
+    }
+    shell.write_file(&contracts_file, serde_json::to_string_pretty(&contracts)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn l1_network_is_recovered_from_db_name() {
+        let mut state = InitState::default();
+        assert_eq!(recorded_l1_network(&state), None);
+        state.db_name = Some(init::db_name("hyperchain", L1Network::Sepolia));
+        assert_eq!(recorded_l1_network(&state), Some(L1Network::Sepolia));
+        state.l1_network = Some(L1Network::Localhost);
+        assert_eq!(recorded_l1_network(&state), Some(L1Network::Localhost));
+    }
+
+    #[test]
+    fn metadata_round_trip() {
+        let metadata = ExportMetadata {
+            name: "hyperchain".to_string(),
+            with_l1: true,
+        };
+        let json = serde_json::to_string_pretty(&metadata).unwrap();
+        let parsed: ExportMetadata = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.name, "hyperchain");
+        assert!(parsed.with_l1);
+    }
+}
@@ -0,0 +1,359 @@
+use std::{collections::HashMap, fmt};
+
+use serde::{Deserialize, Serialize};
+use web3::types::{TransactionParameters, U256, U64};
+use xshell::Shell;
+
+use crate::{consts::FEE_CONFIG_FILE, utils, L1Network};
+
+/// Number of recent blocks used to estimate the fees.
+pub const FEE_HISTORY_BLOCKS: u64 = 10;
+
+/// Gas and fee settings for the transactions sent to L1.
+/// Stored per L1 network in the workspace, so they can be adjusted by the user.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeConfig {
+    /// Send legacy transactions with `gasPrice` instead of EIP-1559 ones.
+    pub legacy: bool,
+    /// Upper bound for `maxFeePerGas` (or `gasPrice` for legacy transactions), in wei.
+    pub max_fee_per_gas_cap: U256,
+    /// Upper bound for `maxPriorityFeePerGas`, in wei.
+    pub max_priority_fee_per_gas_cap: U256,
+    /// Percentile of the priority fees paid in the recent blocks to use.
+    pub priority_fee_percentile: f64,
+    /// Multiplier applied to the `eth_estimateGas` result, in percent.
+    pub gas_limit_multiplier_percent: u64,
+}
+
+impl FeeConfig {
+    fn default_for(l1_network: L1Network) -> Self {
+        let gwei = U256::exp10(9);
+        match l1_network {
+            L1Network::Localhost => Self {
+                legacy: false,
+                max_fee_per_gas_cap: gwei * 1_000,
+                max_priority_fee_per_gas_cap: gwei * 10,
+                priority_fee_percentile: 50.0,
+                gas_limit_multiplier_percent: 150,
+            },
+            L1Network::Sepolia => Self {
+                legacy: false,
+                max_fee_per_gas_cap: gwei * 500,
+                max_priority_fee_per_gas_cap: gwei * 5,
+                priority_fee_percentile: 50.0,
+                gas_limit_multiplier_percent: 130,
+            },
+        }
+    }
+
+    /// Loads the settings for the network from the workspace, falling back to the defaults.
+    pub fn load(l1_network: L1Network) -> anyhow::Result<Self> {
+        let shell = Shell::new()?;
+        let path = utils::base_dir()?.join(FEE_CONFIG_FILE);
+        if !shell.path_exists(&path) {
+            return Ok(Self::default_for(l1_network));
+        }
+        let mut configs: HashMap<L1Network, Self> = serde_json::from_str(&shell.read_file(path)?)?;
+        Ok(configs
+            .remove(&l1_network)
+            .unwrap_or_else(|| Self::default_for(l1_network)))
+    }
+
+    /// Writes the default settings for all the networks, unless the file already exists.
+    pub fn write_defaults(shell: &Shell) -> anyhow::Result<()> {
+        let path = utils::base_dir()?.join(FEE_CONFIG_FILE);
+        if shell.path_exists(&path) {
+            return Ok(());
+        }
+        let configs = HashMap::from([
+            (
+                L1Network::Localhost,
+                Self::default_for(L1Network::Localhost),
+            ),
+            (L1Network::Sepolia, Self::default_for(L1Network::Sepolia)),
+        ]);
+        shell.write_file(path, serde_json::to_string_pretty(&configs)?)?;
+        Ok(())
+    }
+
+    /// Applies the safety multiplier to the estimated gas limit.
+    pub fn gas_limit(&self, estimated: U256) -> U256 {
+        estimated * self.gas_limit_multiplier_percent / 100
+    }
+
+    /// Chooses the fees for a legacy transaction given the current L1 gas price.
+    pub fn legacy_fees(&self, gas_price: U256) -> anyhow::Result<Fees> {
+        if gas_price > self.max_fee_per_gas_cap {
+            anyhow::bail!(
+                "L1 gas price {} exceeds the configured cap of {}",
+                format_gwei(gas_price),
+                format_gwei(self.max_fee_per_gas_cap)
+            );
+        }
+        Ok(Fees::Legacy { gas_price })
+    }
+
+    /// Chooses the fees for an EIP-1559 transaction given the next block base fee and
+    /// the priority fees paid in the recent blocks.
+    pub fn eip1559_fees(&self, base_fee: U256, mut rewards: Vec<U256>) -> anyhow::Result<Fees> {
+        rewards.sort();
+        let priority_fee = rewards
+            .get(rewards.len() / 2)
+            .copied()
+            .unwrap_or_default()
+            .min(self.max_priority_fee_per_gas_cap);
+        // Doubling the base fee keeps the transaction valid for several full blocks in a row.
+        let max_fee = (base_fee * U256::from(2) + priority_fee).min(self.max_fee_per_gas_cap);
+        if max_fee < base_fee + priority_fee {
+            anyhow::bail!(
+                "L1 base fee {} exceeds the configured cap of {}",
+                format_gwei(base_fee),
+                format_gwei(self.max_fee_per_gas_cap)
+            );
+        }
+        Ok(Fees::Eip1559 {
+            max_fee_per_gas: max_fee,
+            max_priority_fee_per_gas: priority_fee,
+        })
+    }
+
+    /// Fees for the replacement of a stuck transaction: bumped enough for L1 to accept it,
+    /// and not lower than the current ones. `None` if the caps don't allow the bump.
+    pub fn bump(&self, sent: Fees, current: Fees) -> Option<Fees> {
+        let bump = |fee: U256| fee * REPLACEMENT_BUMP_PERCENT / 100 + 1;
+        let bumped = match (sent, current) {
+            (Fees::Legacy { gas_price }, current) => Fees::Legacy {
+                gas_price: bump(gas_price).max(current.max_fee_per_gas()),
+            },
+            (
+                Fees::Eip1559 {
+                    max_fee_per_gas,
+                    max_priority_fee_per_gas,
+                },
+                current,
+            ) => {
+                let current_priority_fee = match current {
+                    Fees::Legacy { .. } => U256::zero(),
+                    Fees::Eip1559 {
+                        max_priority_fee_per_gas,
+                        ..
+                    } => max_priority_fee_per_gas,
+                };
+                Fees::Eip1559 {
+                    max_fee_per_gas: bump(max_fee_per_gas).max(current.max_fee_per_gas()),
+                    max_priority_fee_per_gas: bump(max_priority_fee_per_gas)
+                        .max(current_priority_fee),
+                }
+            }
+        };
+        let within_caps = match bumped {
+            Fees::Legacy { gas_price } => gas_price <= self.max_fee_per_gas_cap,
+            Fees::Eip1559 {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            } => {
+                max_fee_per_gas <= self.max_fee_per_gas_cap
+                    && max_priority_fee_per_gas <= self.max_priority_fee_per_gas_cap
+            }
+        };
+        within_caps.then_some(bumped)
+    }
+}
+
+/// Fees of a replacement transaction have to be at least this much higher, in percent.
+const REPLACEMENT_BUMP_PERCENT: u64 = 120;
+
+/// Fees chosen for a transaction.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Fees {
+    Legacy {
+        gas_price: U256,
+    },
+    Eip1559 {
+        max_fee_per_gas: U256,
+        max_priority_fee_per_gas: U256,
+    },
+}
+
+impl Fees {
+    /// Fees the populated transaction was sent with.
+    pub fn of(tx: &TransactionParameters) -> Self {
+        match (tx.max_fee_per_gas, tx.max_priority_fee_per_gas) {
+            (Some(max_fee_per_gas), Some(max_priority_fee_per_gas)) => Self::Eip1559 {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            },
+            _ => Self::Legacy {
+                gas_price: tx.gas_price.expect("Transaction must be populated"),
+            },
+        }
+    }
+
+    /// The most the transaction can pay per unit of gas.
+    pub fn max_fee_per_gas(&self) -> U256 {
+        match self {
+            Self::Legacy { gas_price } => *gas_price,
+            Self::Eip1559 {
+                max_fee_per_gas, ..
+            } => *max_fee_per_gas,
+        }
+    }
+
+    pub fn apply(&self, tx: &mut TransactionParameters) {
+        match *self {
+            Self::Legacy { gas_price } => tx.gas_price = Some(gas_price),
+            Self::Eip1559 {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            } => {
+                tx.transaction_type = Some(U64::from(2));
+                tx.max_fee_per_gas = Some(max_fee_per_gas);
+                tx.max_priority_fee_per_gas = Some(max_priority_fee_per_gas);
+            }
+        }
+    }
+}
+
+impl fmt::Display for Fees {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Legacy { gas_price } => write!(f, "gas price {}", format_gwei(*gas_price)),
+            Self::Eip1559 {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            } => write!(
+                f,
+                "max fee {}, priority fee {}",
+                format_gwei(*max_fee_per_gas),
+                format_gwei(*max_priority_fee_per_gas)
+            ),
+        }
+    }
+}
+
+pub fn format_gwei(amount: U256) -> String {
+    let (gwei, rest) = amount.div_mod(U256::exp10(9));
+    let rest = (rest / U256::exp10(6)).as_u64();
+    format!("{gwei}.{rest:03} gwei")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gwei(amount: u64) -> U256 {
+        U256::exp10(9) * amount
+    }
+
+    fn eip1559(max_fee_per_gas: U256, max_priority_fee_per_gas: U256) -> Fees {
+        Fees::Eip1559 {
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+        }
+    }
+
+    fn assert_fees(fees: Fees, expected: Fees) {
+        assert_eq!(
+            serde_json::to_value(fees).unwrap(),
+            serde_json::to_value(expected).unwrap()
+        );
+    }
+
+    #[test]
+    fn eip1559_fees_use_median_reward_and_double_base_fee() {
+        let config = FeeConfig::default_for(L1Network::Sepolia);
+        let fees = config
+            .eip1559_fees(gwei(10), vec![gwei(3), gwei(1), gwei(2)])
+            .unwrap();
+        assert_fees(fees, eip1559(gwei(22), gwei(2)));
+
+        // No rewards in the recent blocks, and the priority fee is capped.
+        let fees = config.eip1559_fees(gwei(10), vec![]).unwrap();
+        assert_fees(fees, eip1559(gwei(20), U256::zero()));
+        let fees = config.eip1559_fees(gwei(10), vec![gwei(100)]).unwrap();
+        assert_fees(fees, eip1559(gwei(25), gwei(5)));
+
+        // Max fee is capped as long as it still covers the next block.
+        let fees = config.eip1559_fees(gwei(300), vec![gwei(1)]).unwrap();
+        assert_fees(fees, eip1559(gwei(500), gwei(1)));
+        let err = config.eip1559_fees(gwei(500), vec![gwei(1)]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "L1 base fee 500.000 gwei exceeds the configured cap of 500.000 gwei"
+        );
+    }
+
+    #[test]
+    fn legacy_fees_are_capped() {
+        let config = FeeConfig::default_for(L1Network::Sepolia);
+        assert_fees(
+            config.legacy_fees(gwei(500)).unwrap(),
+            Fees::Legacy {
+                gas_price: gwei(500),
+            },
+        );
+        let err = config.legacy_fees(gwei(500) + 1).unwrap_err();
+        assert!(
+            err.to_string().contains("exceeds the configured cap"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn replacement_fees_are_bumped_by_at_least_10_percent() {
+        let config = FeeConfig::default_for(L1Network::Localhost);
+        // L1 nodes reject replacements that don't raise the fees by 10%, rounding up.
+        for fee in [1u64, 9, 10, 11, 99, 1_000_000_007, 7_777_777_777] {
+            let fee = U256::from(fee);
+            let min_bump = (fee * 110 + 99) / 100;
+            let sent = Fees::Legacy { gas_price: fee };
+            let current = Fees::Legacy {
+                gas_price: U256::zero(),
+            };
+            let bumped = config.bump(sent, current).unwrap();
+            assert!(bumped.max_fee_per_gas() >= min_bump, "{fee}: {bumped:?}");
+
+            let bumped = config.bump(eip1559(fee, fee), current).unwrap();
+            let Fees::Eip1559 {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            } = bumped
+            else {
+                panic!("Replacement must keep the transaction type: {bumped:?}");
+            };
+            assert!(max_fee_per_gas >= min_bump, "{fee}: {bumped:?}");
+            assert!(max_priority_fee_per_gas >= min_bump, "{fee}: {bumped:?}");
+        }
+    }
+
+    #[test]
+    fn replacement_fees_follow_current_fees_within_caps() {
+        let config = FeeConfig::default_for(L1Network::Localhost);
+        let sent = eip1559(gwei(10), gwei(1));
+        let bumped = config.bump(sent, eip1559(gwei(50), gwei(3))).unwrap();
+        assert_fees(bumped, eip1559(gwei(50), gwei(3)));
+
+        // Priority fee can't be bumped beyond its cap of 10 gwei.
+        assert!(config.bump(eip1559(gwei(10), gwei(9)), sent).is_none());
+        let legacy = Fees::Legacy {
+            gas_price: gwei(900),
+        };
+        assert!(config.bump(legacy, legacy).is_none());
+    }
+
+    #[test]
+    fn fees_are_formatted_in_gwei() {
+        assert_eq!(format_gwei(gwei(2) + U256::exp10(7) * 5), "2.050 gwei");
+        assert_eq!(format_gwei(U256::exp10(6) - 1), "0.000 gwei");
+    }
+
+    #[test]
+    fn gas_limit_is_multiplied() {
+        let config = FeeConfig::default_for(L1Network::Sepolia);
+        assert_eq!(config.gas_limit(100_000.into()), 130_000.into());
+        assert_eq!(config.gas_limit(1.into()), 1.into());
+        let config = FeeConfig::default_for(L1Network::Localhost);
+        assert_eq!(config.gas_limit(21_000.into()), 31_500.into());
+    }
+}
@@ -0,0 +1,246 @@
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use web3::types::{Address, TransactionReceipt, H256, U256};
+use xshell::Shell;
+
+use crate::{fees, init::L1Stage, utils};
+
+const JOURNAL_FILE_NAME: &str = "transactions.jsonl";
+
+/// Status of a transaction at the moment it's recorded in the journal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionStatus {
+    /// Sent to L1, not mined yet.
+    Pending,
+    /// Superseded by a transaction with the same nonce and bumped fees.
+    Replaced,
+    /// Will never be mined, since its nonce is used by another transaction.
+    Dropped,
+    Succeeded,
+    Failed,
+}
+
+/// Record of a transaction sent to L1. Every transaction is recorded once it's sent,
+/// and again each time its status changes.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Unix timestamp of the moment the status was recorded.
+    pub timestamp: u64,
+    pub stage: Option<L1Stage>,
+    pub description: String,
+    /// Called or deployed contract.
+    pub contract: Option<Address>,
+    pub hash: H256,
+    pub nonce: U256,
+    pub gas_used: Option<U256>,
+    /// Fee paid for the transaction, in wei.
+    pub fee: Option<U256>,
+    pub block_number: Option<u64>,
+    pub success: bool,
+    /// Missing in the entries written by the older versions, which only recorded mined
+    /// transactions.
+    #[serde(default)]
+    status: Option<TransactionStatus>,
+}
+
+impl JournalEntry {
+    /// Entry for the transaction that is not mined.
+    pub fn unmined(
+        stage: Option<L1Stage>,
+        description: &str,
+        to: Option<Address>,
+        hash: H256,
+        nonce: U256,
+        status: TransactionStatus,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            timestamp: utils::unix_timestamp()?,
+            stage,
+            description: description.to_string(),
+            contract: to,
+            hash,
+            nonce,
+            gas_used: None,
+            fee: None,
+            block_number: None,
+            success: false,
+            status: Some(status),
+        })
+    }
+
+    /// Entry for the mined transaction.
+    pub fn new(
+        stage: Option<L1Stage>,
+        description: &str,
+        nonce: U256,
+        receipt: &TransactionReceipt,
+    ) -> anyhow::Result<Self> {
+        let success = receipt.status == Some(1.into());
+        let status = if success {
+            TransactionStatus::Succeeded
+        } else {
+            TransactionStatus::Failed
+        };
+        let fee = receipt
+            .gas_used
+            .zip(receipt.effective_gas_price)
+            .map(|(gas_used, gas_price)| gas_used * gas_price);
+        Ok(Self {
+            timestamp: utils::unix_timestamp()?,
+            stage,
+            description: description.to_string(),
+            contract: receipt.contract_address.or(receipt.to),
+            hash: receipt.transaction_hash,
+            nonce,
+            gas_used: receipt.gas_used,
+            fee,
+            block_number: receipt.block_number.map(|number| number.as_u64()),
+            success,
+            status: Some(status),
+        })
+    }
+
+    pub fn status(&self) -> TransactionStatus {
+        match self.status {
+            Some(status) => status,
+            None if self.success => TransactionStatus::Succeeded,
+            None => TransactionStatus::Failed,
+        }
+    }
+}
+
+/// Append-only journal of the L1 transactions sent on behalf of a hyperchain.
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+}
+
+impl Journal {
+    pub fn new(hyperchain_dir: &Path) -> Self {
+        Self {
+            path: hyperchain_dir.join(JOURNAL_FILE_NAME),
+        }
+    }
+
+    pub fn append(&self, entry: &JournalEntry) -> anyhow::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            Shell::new()?.create_dir(dir)?;
+        }
+        // Written with a single call, so that an interrupted append never leaves the earlier
+        // entries truncated.
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn read(&self) -> anyhow::Result<Vec<JournalEntry>> {
+        let shell = Shell::new()?;
+        if !shell.path_exists(&self.path) {
+            return Ok(Vec::new());
+        }
+        shell
+            .read_file(&self.path)?
+            .lines()
+            .map(|line| Ok(serde_json::from_str(line)?))
+            .collect()
+    }
+}
+
+/// Prints all the L1 transactions sent on behalf of the hyperchain and the total fee paid.
+pub fn show(name: &str) -> anyhow::Result<()> {
+    let shell = Shell::new()?;
+    let hyperchain_dir = utils::hyperchain_dir(name)?;
+    if !shell.path_exists(&hyperchain_dir) {
+        anyhow::bail!("Hyperchain {name} doesn't exist");
+    }
+    let entries = Journal::new(&hyperchain_dir).read()?;
+    if entries.is_empty() {
+        println!("No L1 transactions were sent for hyperchain {name}");
+        return Ok(());
+    }
+
+    // Only the latest status of each transaction is shown, in the order they were sent.
+    let mut transactions: Vec<&JournalEntry> = Vec::new();
+    for entry in &entries {
+        match transactions.iter_mut().find(|tx| tx.hash == entry.hash) {
+            Some(tx) => *tx = entry,
+            None => transactions.push(entry),
+        }
+    }
+
+    let mut total_fee = U256::zero();
+    for entry in &transactions {
+        let stage = entry
+            .stage
+            .map_or("-".to_string(), |stage| stage.to_string());
+        let status = match entry.status() {
+            TransactionStatus::Pending => "pending",
+            TransactionStatus::Replaced => "replaced",
+            TransactionStatus::Dropped => "dropped",
+            TransactionStatus::Succeeded => "ok",
+            TransactionStatus::Failed => "failed",
+        };
+        let Some(block_number) = entry.block_number else {
+            println!(
+                "[{stage}] {}: {status}, {:?} (nonce {})",
+                entry.description, entry.hash, entry.nonce
+            );
+            continue;
+        };
+        let fee = entry.fee.unwrap_or_default();
+        total_fee += fee;
+        println!(
+            "[{stage}] {}: {status}, {:?} (nonce {}, block {block_number}, gas {}, fee {})",
+            entry.description,
+            entry.hash,
+            entry.nonce,
+            entry.gas_used.unwrap_or_default(),
+            fees::format_gwei(fee)
+        );
+    }
+    println!(
+        "{} transactions, {} ETH spent in total",
+        transactions.len(),
+        utils::format_ether(total_fee)
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_are_appended() {
+        let dir = Shell::new().unwrap().create_temp_dir().unwrap();
+        let journal = Journal::new(&dir.path().join("hyperchain"));
+        for nonce in 0..3 {
+            let entry = JournalEntry::unmined(
+                None,
+                "Transfer",
+                None,
+                H256::repeat_byte(nonce),
+                nonce.into(),
+                TransactionStatus::Pending,
+            )
+            .unwrap();
+            journal.append(&entry).unwrap();
+        }
+        let entries = journal.read().unwrap();
+        let nonces: Vec<_> = entries.iter().map(|entry| entry.nonce.as_u64()).collect();
+        assert_eq!(nonces, [0, 1, 2]);
+        let contents = std::fs::read_to_string(&journal.path).unwrap();
+        assert_eq!(contents.lines().count(), 3);
+    }
+}
@@ -0,0 +1,64 @@
+use web3::types::{Address, Bytes, H256, U256};
+
+/// Contract deployed by a pre-signed transaction from an address nobody has the key for
+/// ("Nick's method"). The transaction isn't bound to a chain ID, so the contract gets
+/// the same address on any EVM chain, as long as the deployer hasn't sent anything there yet.
+#[derive(Debug)]
+pub struct KeylessDeployment {
+    pub name: &'static str,
+    /// Address recovered from the signature of the transaction.
+    pub deployer: Address,
+    /// Address of the contract, the first one created by the deployer.
+    pub address: Address,
+    /// Hash of the runtime code of the contract.
+    pub code_hash: H256,
+    gas_price: u64,
+    gas_limit: u64,
+    raw_tx: &'static str,
+}
+
+impl KeylessDeployment {
+    /// Amount the deployer has to be funded with for the transaction to go through.
+    pub fn cost(&self) -> U256 {
+        U256::from(self.gas_price) * U256::from(self.gas_limit)
+    }
+
+    pub fn raw_tx(&self) -> Bytes {
+        hex::decode(self.raw_tx)
+            .expect("Keyless deployment transaction is valid hex")
+            .into()
+    }
+}
+
+/// Singleton CREATE2 factory from EIP-2470.
+pub fn singleton_factory() -> KeylessDeployment {
+    KeylessDeployment {
+        name: "SingletonFactory",
+        deployer: "Bb6e024b9cFFACB947A71991E386681B1Cd1477D".parse().unwrap(),
+        address: "ce0042B868300000d44A59004Da54A005ffdcf9f".parse().unwrap(),
+        code_hash: "c4d5542b53a8b779595a20a8ddd60e58a6c49d3c3decc2df83ced1c69c8ca807"
+            .parse()
+            .unwrap(),
+        gas_price: 100_000_000_000,
+        gas_limit: 247_000,
+        raw_tx: SINGLETON_FACTORY_TX,
+    }
+}
+
+/// Canonical address of Multicall3, the same on all the chains it's deployed to.
+pub fn multicall3_address() -> Address {
+    "cA11bde05977b3631167028862bE2a173976CA11".parse().unwrap()
+}
+
+const SINGLETON_FACTORY_TX: &str = concat!(
+    "f9016c8085174876e8008303c4d88080b90154",
+    "608060405234801561001057600080fd5b50610134806100206000396000f3fe6080604052348015600f5760",
+    "0080fd5b506004361060285760003560e01c80634af63f0214602d575b600080fd5b60cf6004803603604081",
+    "1015604157600080fd5b810190602081018135640100000000811115605b57600080fd5b8201836020820111",
+    "15606c57600080fd5b80359060200191846001830284011164010000000083111715608d57600080fd5b9190",
+    "8080601f01602080910402602001604051908101604052809392919081815260200183838082843760009201",
+    "9190915250929550509135925060eb915050565b604080516001600160a01b03909216825251908190036020",
+    "0190f35b6000818351602085016000f5939250505056fea26469706673582212206b44f8a82cb6b156bfcc3d",
+    "c6aadd6df4eefd204bc928a4397fd15dacf6d5320564736f6c63430006020033",
+    "1b83247000822470",
+);
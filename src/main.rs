@@ -1,26 +1,51 @@
 #![allow(dead_code)]
 
-use std::{collections::HashMap, time::SystemTime};
+use std::{collections::HashMap, path::PathBuf, time::SystemTime};
 
+use addresses::AddressesFormat;
 use anyhow::Context;
-use clap::{Parser, Subcommand, ValueEnum};
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
 use derive_more::Display;
 use init::Init;
 use prerequisites::check_prerequisites;
 use serde::{Deserialize, Serialize};
-use web3::ethabi::Address;
+use web3::{
+    ethabi::Address,
+    signing::keccak256,
+    types::{H256, U256},
+};
 use xshell::{cmd, Shell};
 
+mod addresses;
+mod archive;
+mod confirmations;
 mod consts;
 mod contracts;
+mod db;
+mod export;
+mod fees;
 mod init;
+mod journal;
+mod keyless;
+mod manifest;
+mod offline;
 mod prerequisites;
+mod revert;
+mod signer;
 mod utils;
+mod wallets;
 mod web3_client;
 
-use consts::{DOCKER_IMAGE, DOCKER_TAG, GIT_REPO, GIT_REVISION};
+use consts::{DOCKER_IMAGE, DOCKER_TAG, GIT_REPO, GIT_REVISION, PREREQUISITE_CONTRACTS_FILE};
 
-use crate::{consts::localhost_rich_wallet, contracts::Contracts, web3_client::Web3Client};
+use crate::{
+    confirmations::ConfirmationConfig,
+    consts::localhost_rich_wallet,
+    fees::FeeConfig,
+    signer::{LocalSigner, Signer},
+    wallets::{KeyImport, KeySource, WalletRole},
+    web3_client::Web3Client,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WorkspaceMetadata {
@@ -39,49 +64,300 @@ struct Cli {
 enum Commands {
     /// Either initializes a new hyperchain or continues an existing
     /// initialization process.
-    Init {
+    Init(Box<InitArgs>),
+    /// Prints the location for a certain hyperchain data.
+    Where {
+        /// Name of the hyperchain.
+        name: String,
+    },
+    /// Packs the hyperchain into an archive that can be imported on another machine.
+    Export {
         /// Name of the hyperchain.
         name: String,
+        /// Path to the archive. By default, created in the current directory.
         #[arg(long)]
-        /// L1 network
-        l1: L1Network,
-        /// L2 chain ID
+        output: Option<PathBuf>,
+    },
+    /// Prints the addresses, chain IDs and RPC URLs of the hyperchain for the downstream tools.
+    ExportAddresses {
+        /// Name of the hyperchain.
+        name: String,
+        #[arg(long, value_enum, default_value = "json")]
+        format: AddressesFormat,
+        /// Public URL of the hyperchain RPC. By default, the local server URL.
         #[arg(long)]
-        chain_id: u64,
-        /// (Sepolia only) URL of Web3 API
-        #[arg(long, required_if_eq("l1", "sepolia"))]
-        web3_rpc: Option<url::Url>,
+        l2_rpc: Option<url::Url>,
+        /// Include the L1 RPC URL, which may contain an API key. Always included for localhost.
+        #[arg(long)]
+        with_l1_rpc: bool,
+        /// File to write to instead of printing.
+        #[arg(long)]
+        output: Option<PathBuf>,
     },
-    /// Prints the location for a certain hyperchain data.
-    Where {
+    /// Recreates a hyperchain from an archive created by `export`.
+    Import {
+        /// Path to the archive.
+        archive: PathBuf,
+        /// Name for the imported hyperchain. By default, the original name is used.
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Signs transactions written by `init` in the offline signing mode.
+    /// Doesn't require network access.
+    Sign {
+        /// File with unsigned transactions.
+        file: PathBuf,
+        /// Keystore with the key to sign transactions with.
+        #[arg(long)]
+        keystore: PathBuf,
+        /// Path to the file with signed transactions. By default, created in the current directory.
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Sends transactions signed by `sign` and marks the corresponding stage as completed.
+    Broadcast {
+        /// Name of the hyperchain.
+        name: String,
+        /// File with signed transactions.
+        file: PathBuf,
+    },
+    /// Manages the hyperchain database.
+    Db {
+        #[command(subcommand)]
+        command: DbCommands,
+    },
+    /// Shows the L1 transactions sent for the hyperchain.
+    Txs {
+        /// Name of the hyperchain.
+        name: String,
+    },
+    /// Checks that the L1 contracts of the hyperchain still match the recorded deployment.
+    VerifyDeployment {
         /// Name of the hyperchain.
         name: String,
     },
+    /// Manages the hyperchain wallets.
+    Wallets {
+        /// Name of the hyperchain.
+        name: String,
+        #[command(subcommand)]
+        command: WalletsCommands,
+    },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Args, Debug)]
+#[command(group = ArgGroup::new("admin_signer").args(["admin_key", "remote_signer"]))]
+pub struct InitArgs {
+    /// Name of the hyperchain.
+    name: String,
+    #[arg(long)]
+    /// L1 network
+    l1: L1Network,
+    /// L2 chain ID
+    #[arg(long)]
+    chain_id: u64,
+    /// (Sepolia only) URL of Web3 API
+    #[arg(long, required_if_eq("l1", "sepolia"))]
+    web3_rpc: Option<url::Url>,
+    /// Also initialize the prover database.
+    #[arg(long)]
+    with_prover: bool,
+    /// Existing admin key instead of a generated one:
+    /// `keystore:<path>`, `hex:<path>` or `mnemonic:<path>[:<derivation path>]`.
+    #[arg(long)]
+    admin_key: Option<KeySource>,
+    /// Expected address of the provided admin key, or the admin address held by the remote signer.
+    #[arg(long, requires = "admin_signer")]
+    admin_address: Option<Address>,
+    /// URL of a remote signer (`eth_signTransaction`) holding the admin key.
+    /// The admin key is then never generated or seen by the wizard.
+    #[arg(long, requires = "admin_address", conflicts_with = "admin_key")]
+    remote_signer: Option<url::Url>,
+    /// Existing operator key instead of a generated one, same format as `--admin-key`.
+    #[arg(long)]
+    operator_key: Option<KeySource>,
+    /// Expected address of the provided operator key.
+    #[arg(long, requires = "operator_key")]
+    operator_address: Option<Address>,
+    /// File with the mnemonic to derive the wallets from, instead of generating a new one.
+    #[arg(long)]
+    mnemonic: Option<PathBuf>,
+    /// Write admin transactions to files to be signed offline, instead of sending them.
+    /// Recorded for the later runs, `--offline-signing=false` switches back to sending them.
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    offline_signing: Option<bool>,
+    /// Salt for the contracts deployed with CREATE2, determines their addresses.
+    /// Derived from the hyperchain name and chain ID by default.
+    #[arg(long)]
+    create2_salt: Option<H256>,
+    /// File with the addresses of L1 contracts deployed by other tooling: a JSON object
+    /// mapping names to addresses, or `CONTRACTS_<NAME>_ADDR=<address>` lines.
+    /// The verifier and L1 contracts are then not deployed. Genesis and L2 deployment are
+    /// not supported by the wizard yet, so the hyperchain stays partially initialized.
+    #[arg(long)]
+    l1_contracts: Option<PathBuf>,
+}
+
+impl InitArgs {
+    /// Existing keys provided by the user, per wallet role.
+    fn key_imports(&self) -> HashMap<WalletRole, KeyImport> {
+        let mut key_imports = HashMap::new();
+        if let Some(source) = &self.admin_key {
+            let import = KeyImport {
+                source: source.clone(),
+                expected_address: self.admin_address,
+            };
+            key_imports.insert(WalletRole::Admin, import);
+        }
+        if let Some(source) = &self.operator_key {
+            let import = KeyImport {
+                source: source.clone(),
+                expected_address: self.operator_address,
+            };
+            key_imports.insert(WalletRole::Operator, import);
+        }
+        key_imports
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum DbCommands {
+    /// Dumps the hyperchain database and its state into an archive.
+    Backup {
+        /// Name of the hyperchain.
+        name: String,
+        /// Path to the archive. By default, created in the current directory.
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Restores the hyperchain database and its state from an archive.
+    Restore {
+        /// Name of the hyperchain.
+        name: String,
+        /// Path to the archive created by `db backup`.
+        file: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum WalletsCommands {
+    /// Shows the addresses and balances of the wallets.
+    Show,
+    /// Sends ETH to the wallets.
+    Fund {
+        /// Wallet to fund. By default, all the wallets are funded.
+        #[arg(long)]
+        role: Option<WalletRole>,
+        /// Amount of ETH to send to every wallet.
+        #[arg(long, default_value = "1")]
+        amount: String,
+        /// Key to send the funds from, same format as `init --admin-key`.
+        /// Defaults to the rich wallet on localhost.
+        #[arg(long)]
+        from: Option<KeySource>,
+    },
+    /// Transfers all the funds from the wallets to the address.
+    Sweep {
+        /// Address to send the funds to.
+        #[arg(long)]
+        to: Address,
+        /// Wallet to sweep. By default, all the wallets are swept.
+        #[arg(long)]
+        role: Option<WalletRole>,
+    },
+    /// Replaces the wallet key with a newly generated one.
+    Rotate {
+        /// Wallet to rotate.
+        #[arg(long)]
+        role: WalletRole,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PrerequisiteContracts {
     pub multicall3: Address,
     pub create2_factory: Address,
 }
 
+impl PrerequisiteContracts {
+    /// Records the addresses of the prerequisite contracts on the network in the workspace.
+    pub(crate) fn save(&self, shell: &Shell, l1_network: L1Network) -> anyhow::Result<()> {
+        let path = utils::base_dir()?.join(PREREQUISITE_CONTRACTS_FILE);
+        let mut contracts: HashMap<L1Network, Self> =
+            serde_json::from_str(&shell.read_file(&path)?)?;
+        contracts.insert(l1_network, self.clone());
+        shell.write_file(path, serde_json::to_string_pretty(&contracts)?)?;
+        Ok(())
+    }
+
+    /// Addresses of the prerequisite contracts on the network, recorded in the workspace.
+    pub(crate) fn load(shell: &Shell, l1_network: L1Network) -> anyhow::Result<Self> {
+        let path = utils::base_dir()?.join(PREREQUISITE_CONTRACTS_FILE);
+        let mut contracts: HashMap<L1Network, Self> =
+            serde_json::from_str(&shell.read_file(path)?)?;
+        contracts
+            .remove(&l1_network)
+            .with_context(|| format!("No prerequisite contracts for {l1_network}"))
+    }
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     human_panic::setup_panic!();
 
     let opts = Cli::parse();
     match opts.command {
-        Commands::Init {
-            name,
-            l1,
-            chain_id,
-            web3_rpc,
-        } => init(name, l1, chain_id, web3_rpc).await,
+        Commands::Init(args) => init(*args).await,
         Commands::Where { name } => {
             let dir = utils::hyperchain_dir(&name)?.to_string_lossy().to_string();
             println!("{}", dir);
             Ok(())
         }
+        Commands::Export { name, output } => {
+            let shell = Shell::new()?;
+            check_prerequisites(&shell);
+            start_containers(&shell)?;
+            init::wait_for_db().await?;
+            export::export(&name, output).await
+        }
+        Commands::ExportAddresses {
+            name,
+            format,
+            l2_rpc,
+            with_l1_rpc,
+            output,
+        } => addresses::export(
+            &name,
+            format,
+            l2_rpc.map(|url| url.to_string()),
+            with_l1_rpc,
+            output,
+        ),
+        Commands::Import { archive, name } => {
+            let shell = Shell::new()?;
+            check_prerequisites(&shell);
+            init_base_dir(&shell).await?;
+            start_containers(&shell)?;
+            init::wait_for_db().await?;
+            export::import(&archive, name).await
+        }
+        Commands::Sign {
+            file,
+            keystore,
+            output,
+        } => offline::sign(&file, &keystore, output).await,
+        Commands::Broadcast { name, file } => offline::broadcast(&name, &file).await,
+        Commands::Db { command } => db(command).await,
+        Commands::Txs { name } => journal::show(&name),
+        Commands::VerifyDeployment { name } => manifest::verify(&name).await,
+        Commands::Wallets { name, command } => match command {
+            WalletsCommands::Show => wallets::show(&name).await,
+            WalletsCommands::Fund { role, amount, from } => {
+                wallets::fund(&name, role, &amount, from).await
+            }
+            WalletsCommands::Sweep { to, role } => wallets::sweep(&name, role, to).await,
+            WalletsCommands::Rotate { role } => wallets::rotate(&name, role).await,
+        },
     }
 }
 
@@ -142,58 +418,32 @@ async fn init_base_dir(shell: &Shell) -> anyhow::Result<()> {
 
     // Docker-compose
     println!("Copying docker-compose template...");
-    const DOCKER_COMPOSE_FILE: &'static str =
-        include_str!("../assets/docker-compose-template.yaml");
+    const DOCKER_COMPOSE_FILE: &str = include_str!("../assets/docker-compose-template.yaml");
     shell.write_file(base_dir.join("docker-compose.yaml"), DOCKER_COMPOSE_FILE)?;
 
+    FeeConfig::write_defaults(shell)?;
+    ConfirmationConfig::write_defaults(shell)?;
+
     // Deploy multicall3 and create2factory on localhost.
-    start_containers(&shell)?;
+    start_containers(shell)?;
     println!("Deploying prerequisite contracts to localhost L1");
-    let deployer = Web3Client::new(init::LOCALHOST_WEB3)?;
-    deployer.wait_until_up().await?;
-    let contracts = Contracts::new(&shell, base_dir.clone());
-    let multicall3 = contracts.multicall3().context("load multicall3")?;
-    let create2_factory = contracts
-        .create2_factory()
-        .context("load create2_factory")?;
-    let multicall3 = deployer
-        .deploy(
-            localhost_rich_wallet(),
-            &multicall3.raw_abi,
-            multicall3.bytecode,
-            (),
-        )
-        .await
-        .context("deploy multicall3")?;
-    println!("Deployed Multicall3 contract to localhost");
-    let create2_factory = deployer
-        .deploy(
-            localhost_rich_wallet(),
-            &create2_factory.raw_abi,
-            create2_factory.bytecode,
-            (),
-        )
+    let deployer = Web3Client::new(init::LOCALHOST_WEB3, L1Network::Localhost).await?;
+    let rich_wallet = LocalSigner::new(localhost_rich_wallet())?;
+    let localhost_contracts = deploy_prerequisite_contracts(&deployer, &rich_wallet)
         .await
-        .context("deploy create2 factory")?;
-    println!("Deployed Create2 contract to localhost");
+        .context("deploy prerequisite contracts")?;
     let mut prerequisite_contracts = HashMap::new();
-    prerequisite_contracts.insert(
-        L1Network::Localhost,
-        PrerequisiteContracts {
-            create2_factory: create2_factory.address(),
-            multicall3: multicall3.address(),
-        },
-    );
+    prerequisite_contracts.insert(L1Network::Localhost, localhost_contracts);
     prerequisite_contracts.insert(
         L1Network::Sepolia,
         PrerequisiteContracts {
-            create2_factory: "ce0042b868300000d44a59004da54a005ffdcf9f".parse().unwrap(),
-            multicall3: "cA11bde05977b3631167028862bE2a173976CA11".parse().unwrap(),
+            create2_factory: keyless::singleton_factory().address,
+            multicall3: keyless::multicall3_address(),
         },
     );
     let encoded_contracts = serde_json::to_string_pretty(&prerequisite_contracts).unwrap();
     shell.write_file(
-        base_dir.join(".prerequisite_contracts.json"),
+        base_dir.join(PREREQUISITE_CONTRACTS_FILE),
         &encoded_contracts,
     )?;
 
@@ -206,6 +456,83 @@ async fn init_base_dir(shell: &Shell) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Makes sure that the CREATE2 factory and Multicall3 are deployed to L1, deploying the factory
+/// with the funds of `funder` if it's missing.
+///
+/// Both contracts must be at their canonical addresses, which only their keyless transactions
+/// can create. The keyless transaction of Multicall3 is not bundled with the wizard, so it has
+/// to be deployed beforehand.
+async fn deploy_prerequisite_contracts(
+    client: &Web3Client,
+    funder: &dyn Signer,
+) -> anyhow::Result<PrerequisiteContracts> {
+    let multicall3 = keyless::multicall3_address();
+    if client.code(multicall3).await?.0.is_empty() {
+        anyhow::bail!(
+            "Multicall3 is not deployed to its canonical address {multicall3:?}. Broadcast its pre-signed deployment transaction first, see https://github.com/mds1/multicall3#new-deployments"
+        );
+    }
+    let create2_factory = client
+        .deploy_keyless(funder, &keyless::singleton_factory())
+        .await?;
+    Ok(PrerequisiteContracts {
+        multicall3,
+        create2_factory,
+    })
+}
+
+/// Makes sure that the prerequisite contracts are actually deployed to L1 at their canonical
+/// addresses, and that the factory code matches its keyless deployment.
+async fn verify_prerequisite_contracts(
+    client: &Web3Client,
+    contracts: &PrerequisiteContracts,
+) -> anyhow::Result<()> {
+    let factory = keyless::singleton_factory();
+    let code = client.code(contracts.create2_factory).await?;
+    if code.0.is_empty() {
+        anyhow::bail!("No CREATE2 factory at {:?}", contracts.create2_factory);
+    }
+    if keccak256(&code.0) != factory.code_hash.0 {
+        anyhow::bail!(
+            "Code at {:?} doesn't match the CREATE2 factory",
+            contracts.create2_factory
+        );
+    }
+
+    if contracts.multicall3 != keyless::multicall3_address() {
+        anyhow::bail!(
+            "Multicall3 at {:?} is not at its canonical address {:?}",
+            contracts.multicall3,
+            keyless::multicall3_address()
+        );
+    }
+    let code = client.code(contracts.multicall3).await?;
+    if code.0.is_empty() {
+        anyhow::bail!("No Multicall3 at {:?}", contracts.multicall3);
+    }
+    // The wizard reads L1 through `aggregate3`, so it's checked end to end.
+    let get_chain_id = web3::ethabi::short_signature("getChainId", &[]);
+    let outputs = client
+        .multicall(
+            contracts.multicall3,
+            vec![(contracts.multicall3, get_chain_id.to_vec().into())],
+        )
+        .await
+        .with_context(|| format!("Multicall3 at {:?} is not usable", contracts.multicall3))?;
+    let chain_id = outputs
+        .into_iter()
+        .flatten()
+        .find(|output| output.0.len() == 32)
+        .map(|output| U256::from_big_endian(&output.0));
+    if chain_id != Some(client.chain_id().into()) {
+        anyhow::bail!(
+            "Contract at {:?} doesn't behave like Multicall3",
+            contracts.multicall3
+        );
+    }
+    Ok(())
+}
+
 fn start_containers(shell: &Shell) -> anyhow::Result<()> {
     let base_dir = utils::base_dir()?;
     shell.change_dir(base_dir);
@@ -213,25 +540,64 @@ fn start_containers(shell: &Shell) -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn init(
-    name: String,
-    l1_network: L1Network,
-    chain_id: u64,
-    web3_rpc: Option<url::Url>,
-) -> anyhow::Result<()> {
+async fn init(args: InitArgs) -> anyhow::Result<()> {
     let shell = Shell::new()?;
     check_prerequisites(&shell);
     init_base_dir(&shell).await?;
     start_containers(&shell)?;
 
-    let init = Init::new(name, l1_network, chain_id, web3_rpc)?;
+    let init = Init::new(args)?;
     init.init().await?;
 
     Ok(())
 }
 
+async fn db(command: DbCommands) -> anyhow::Result<()> {
+    let shell = Shell::new()?;
+    check_prerequisites(&shell);
+    start_containers(&shell)?;
+    init::wait_for_db().await?;
+
+    match command {
+        DbCommands::Backup { name, output } => db::backup(&name, output).await,
+        DbCommands::Restore { name, file } => db::restore(&name, &file).await,
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize, Display, ValueEnum)]
 enum L1Network {
     Localhost,
     Sepolia,
 }
+
+impl L1Network {
+    /// Chain ID of the network, checked against Web3 RPC before sending any transactions.
+    pub fn chain_id(self) -> u64 {
+        match self {
+            // Chain ID of the local geth node from the docker-compose setup.
+            Self::Localhost => 9,
+            Self::Sepolia => 11_155_111,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn admin_address_requires_admin_signer() {
+        let args = ["zk_stack_wizard", "init", "hyperchain", "--l1", "localhost"];
+        let address = "--admin-address=0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266";
+        let parse = |extra: &[&str]| {
+            Cli::try_parse_from(args.iter().chain(["--chain-id", "270"].iter()).chain(extra))
+        };
+
+        let err = parse(&[address]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::MissingRequiredArgument);
+        assert!(err.to_string().contains("--admin-key"), "{err}");
+        parse(&[address, "--admin-key=hex:admin.key"]).unwrap();
+        parse(&[address, "--remote-signer=http://localhost:8545"]).unwrap();
+        parse(&["--admin-key=hex:admin.key"]).unwrap();
+    }
+}
@@ -0,0 +1,446 @@
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+use web3::{
+    ethabi,
+    signing::keccak256,
+    types::{Address, Bytes, H256, U256},
+};
+use xshell::Shell;
+
+use crate::{init::InitState, utils, web3_client::Web3Client, L1Network, PrerequisiteContracts};
+
+/// Version of the manifest format, bumped on incompatible changes.
+pub const MANIFEST_VERSION: u32 = 1;
+pub(crate) const MANIFEST_FILE_NAME: &str = "deployment.json";
+
+/// All the L1 contracts the hyperchain relies on. Meant to be consumed by the later stages
+/// and by external tools, so it only contains stable, self-describing data.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeploymentManifest {
+    pub version: u32,
+    pub l1_network: L1Network,
+    pub l1_chain_id: u64,
+    pub contracts: BTreeMap<String, DeployedContract>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeployedContract {
+    pub address: Address,
+    /// Not known for contracts deployed outside of the wizard.
+    pub deploy_tx: Option<H256>,
+    pub block_number: Option<u64>,
+    /// ABI-encoded constructor arguments.
+    pub constructor_args: Bytes,
+    /// Hash of the runtime code at the address.
+    pub bytecode_hash: H256,
+    /// Implementation behind the contract, if it's an EIP-1967 proxy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub implementation: Option<Address>,
+    /// Admin of the contract, if it's an EIP-1967 proxy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy_admin: Option<Address>,
+    /// Result of `owner()`, if the contract is ownable.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<Address>,
+    /// Result of `getGovernor()`, if the contract is a hyperchain diamond proxy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub governor: Option<Address>,
+    /// Result of `getAdmin()`, if the contract is a hyperchain diamond proxy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin: Option<Address>,
+}
+
+/// Part of the contract state on L1 which is expected to stay as deployed.
+#[derive(Debug, PartialEq, Eq)]
+pub struct OnChainContract {
+    pub bytecode_hash: H256,
+    pub implementation: Option<Address>,
+    pub proxy_admin: Option<Address>,
+    pub owner: Option<Address>,
+    pub governor: Option<Address>,
+    pub admin: Option<Address>,
+}
+
+/// View functions returning the roles of the contract. Contracts without them revert,
+/// which is not an error here.
+const ROLE_GETTERS: [&str; 3] = ["owner", "getGovernor", "getAdmin"];
+
+impl OnChainContract {
+    pub async fn read(client: &Web3Client, address: Address) -> anyhow::Result<Self> {
+        let mut roles = Vec::with_capacity(ROLE_GETTERS.len());
+        for getter in ROLE_GETTERS {
+            roles.push(client.call(address, getter_call(getter)).await.ok());
+        }
+        Self::read_state(client, address, roles).await
+    }
+
+    /// Reads the contracts, executing their view calls in a single call to Multicall3.
+    pub async fn read_all(
+        client: &Web3Client,
+        multicall3: Address,
+        addresses: &[Address],
+    ) -> anyhow::Result<Vec<Self>> {
+        let calls = addresses
+            .iter()
+            .flat_map(|address| ROLE_GETTERS.map(|getter| (*address, getter_call(getter))))
+            .collect();
+        let outputs = client.multicall(multicall3, calls).await?;
+        let mut contracts = Vec::with_capacity(addresses.len());
+        for (address, roles) in addresses.iter().zip(outputs.chunks(ROLE_GETTERS.len())) {
+            contracts.push(Self::read_state(client, *address, roles.to_vec()).await?);
+        }
+        Ok(contracts)
+    }
+
+    /// Reads the code and the proxy slots, which are not available through view calls.
+    async fn read_state(
+        client: &Web3Client,
+        address: Address,
+        roles: Vec<Option<Bytes>>,
+    ) -> anyhow::Result<Self> {
+        let [owner, governor, admin] = <[_; 3]>::try_from(roles)
+            .map_err(|_| anyhow::anyhow!("Unexpected number of role getters"))?
+            .map(|output| output.and_then(decode_address));
+        let bytecode_hash = keccak256(&client.code(address).await?.0).into();
+        let implementation = eip1967_slot(client, address, "eip1967.proxy.implementation").await?;
+        let proxy_admin = eip1967_slot(client, address, "eip1967.proxy.admin").await?;
+        Ok(Self {
+            bytecode_hash,
+            implementation,
+            proxy_admin,
+            owner,
+            governor,
+            admin,
+        })
+    }
+}
+
+fn getter_call(name: &str) -> Bytes {
+    ethabi::short_signature(name, &[]).to_vec().into()
+}
+
+/// Address returned by a view call, if the call has returned one.
+fn decode_address(output: Bytes) -> Option<Address> {
+    (output.0.len() == 32).then(|| Address::from_slice(&output.0[12..]))
+}
+
+/// Address stored in the EIP-1967 slot, `keccak256(name) - 1`, if it's set.
+async fn eip1967_slot(
+    client: &Web3Client,
+    address: Address,
+    name: &str,
+) -> anyhow::Result<Option<Address>> {
+    let slot = U256::from_big_endian(&keccak256(name.as_bytes())) - 1;
+    let value = client.storage(address, slot).await?;
+    Ok((!value.is_zero()).then(|| Address::from_slice(&value.0[12..])))
+}
+
+impl DeploymentManifest {
+    pub fn new(l1_network: L1Network) -> Self {
+        Self {
+            version: MANIFEST_VERSION,
+            l1_network,
+            l1_chain_id: l1_network.chain_id(),
+            contracts: BTreeMap::new(),
+        }
+    }
+
+    /// Loads the manifest of the hyperchain, if anything has been deployed yet.
+    pub fn load(shell: &Shell, hyperchain_dir: &Path) -> anyhow::Result<Option<Self>> {
+        let path = hyperchain_dir.join(MANIFEST_FILE_NAME);
+        if !shell.path_exists(&path) {
+            return Ok(None);
+        }
+        let manifest: Self = serde_json::from_str(&shell.read_file(&path)?)?;
+        if manifest.version > MANIFEST_VERSION {
+            anyhow::bail!(
+                "Deployment manifest {path:?} has version {}, while only {MANIFEST_VERSION} is supported",
+                manifest.version
+            );
+        }
+        Ok(Some(manifest))
+    }
+
+    pub fn save(&self, shell: &Shell, hyperchain_dir: &Path) -> anyhow::Result<()> {
+        shell.write_file(
+            hyperchain_dir.join(MANIFEST_FILE_NAME),
+            serde_json::to_string_pretty(self)?,
+        )?;
+        Ok(())
+    }
+
+    /// Adds the contract to the manifest of the hyperchain. The contract recorded under the same
+    /// name is only replaced by one with another address or code if `replace` is set, i.e. it's
+    /// been deployed again, so that any drift stays visible. If the contract is already recorded
+    /// at the same address, the deployment transaction is kept when it's not known anymore
+    /// (e.g. a CREATE2 deployment was skipped), and the manifest is not rewritten if nothing
+    /// has changed.
+    pub fn record(
+        shell: &Shell,
+        hyperchain_dir: &Path,
+        l1_network: L1Network,
+        name: &str,
+        mut contract: DeployedContract,
+        replace: bool,
+    ) -> anyhow::Result<()> {
+        let mut manifest =
+            Self::load(shell, hyperchain_dir)?.unwrap_or_else(|| Self::new(l1_network));
+        if let Some(recorded) = manifest.contracts.get(name) {
+            let redeployed = recorded.address != contract.address
+                || recorded.bytecode_hash != contract.bytecode_hash;
+            if redeployed && !replace {
+                anyhow::bail!(
+                    "{name} is recorded at {:?} with code hash {:?}, not at {:?} with code hash {:?}. Run `verify-deployment` to see what has changed on L1",
+                    recorded.address,
+                    recorded.bytecode_hash,
+                    contract.address,
+                    contract.bytecode_hash
+                );
+            }
+            if contract.deploy_tx.is_none() && recorded.address == contract.address {
+                contract.deploy_tx = recorded.deploy_tx;
+                contract.block_number = recorded.block_number;
+            }
+            if *recorded == contract {
+                return Ok(());
+            }
+        }
+        manifest.contracts.insert(name.to_string(), contract);
+        manifest.save(shell, hyperchain_dir)
+    }
+
+    pub fn address(&self, name: &str) -> Option<Address> {
+        self.contracts.get(name).map(|contract| contract.address)
+    }
+}
+
+/// Compares the contracts on L1 with the deployment manifest of the hyperchain and reports
+/// every mismatch. Fails if there are any.
+pub async fn verify(name: &str) -> anyhow::Result<()> {
+    let shell = Shell::new()?;
+    let hyperchain_dir = utils::hyperchain_dir(name)?;
+    let Some(manifest) = DeploymentManifest::load(&shell, &hyperchain_dir)? else {
+        anyhow::bail!("Hyperchain {name} doesn't have any contracts deployed");
+    };
+    let state = InitState::load(&shell, &hyperchain_dir)?;
+    let Some(web3_rpc) = &state.web3_rpc else {
+        anyhow::bail!("Hyperchain {name} is not initialized");
+    };
+    let client = Web3Client::new(web3_rpc, manifest.l1_network).await?;
+    let multicall3 = match manifest.address("Multicall3") {
+        Some(address) => address,
+        None => PrerequisiteContracts::load(&shell, manifest.l1_network)?.multicall3,
+    };
+    let addresses: Vec<_> = manifest
+        .contracts
+        .values()
+        .map(|contract| contract.address)
+        .collect();
+    let on_chain = OnChainContract::read_all(&client, multicall3, &addresses).await?;
+
+    let mut mismatches = 0;
+    for ((contract_name, recorded), actual) in manifest.contracts.iter().zip(on_chain) {
+        let address = recorded.address;
+        let mut report = Vec::new();
+        if actual.bytecode_hash != recorded.bytecode_hash {
+            report.push(format!(
+                "bytecode hash is {:?} instead of {:?}",
+                actual.bytecode_hash, recorded.bytecode_hash
+            ));
+        }
+        for (field, expected, actual) in [
+            (
+                "implementation",
+                recorded.implementation,
+                actual.implementation,
+            ),
+            ("proxy admin", recorded.proxy_admin, actual.proxy_admin),
+            ("owner", recorded.owner, actual.owner),
+            ("governor", recorded.governor, actual.governor),
+            ("admin", recorded.admin, actual.admin),
+        ] {
+            if expected != actual {
+                report.push(format!(
+                    "{field} is {} instead of {}",
+                    format_address(actual),
+                    format_address(expected)
+                ));
+            }
+        }
+
+        if report.is_empty() {
+            println!("{contract_name} at {address:?}: ok");
+        } else {
+            println!("{contract_name} at {address:?}: {}", report.join(", "));
+            mismatches += report.len();
+        }
+    }
+    if mismatches > 0 {
+        anyhow::bail!(
+            "L1 contracts of hyperchain {name} have {mismatches} mismatches with the deployment"
+        );
+    }
+    println!("L1 contracts of hyperchain {name} match the deployment");
+    Ok(())
+}
+
+fn format_address(address: Option<Address>) -> String {
+    address.map_or("not set".to_string(), |address| format!("{address:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contract(address: Address, bytecode_hash: H256) -> DeployedContract {
+        DeployedContract {
+            address,
+            deploy_tx: None,
+            block_number: None,
+            constructor_args: Bytes::default(),
+            bytecode_hash,
+            implementation: None,
+            proxy_admin: None,
+            owner: None,
+            governor: None,
+            admin: None,
+        }
+    }
+
+    #[test]
+    fn manifest_round_trip() {
+        let mut manifest = DeploymentManifest::new(L1Network::Sepolia);
+        let mut verifier = contract(Address::repeat_byte(1), H256::repeat_byte(2));
+        verifier.deploy_tx = Some(H256::repeat_byte(3));
+        verifier.block_number = Some(42);
+        verifier.constructor_args = vec![4, 5].into();
+        verifier.owner = Some(Address::repeat_byte(6));
+        manifest.contracts.insert("Verifier".to_string(), verifier);
+
+        let json: serde_json::Value = serde_json::to_value(&manifest).unwrap();
+        assert_eq!(json["version"], MANIFEST_VERSION);
+        assert_eq!(json["l1_chain_id"], 11_155_111);
+        // Unset proxy fields are omitted.
+        assert!(json["contracts"]["Verifier"]
+            .get("implementation")
+            .is_none());
+
+        let shell = Shell::new().unwrap();
+        let dir = shell.create_temp_dir().unwrap();
+        manifest.save(&shell, dir.path()).unwrap();
+        let loaded = DeploymentManifest::load(&shell, dir.path())
+            .unwrap()
+            .unwrap();
+        assert_eq!(loaded.version, MANIFEST_VERSION);
+        assert_eq!(loaded.l1_network, L1Network::Sepolia);
+        assert_eq!(loaded.contracts, manifest.contracts);
+    }
+
+    #[test]
+    fn newer_manifest_version_is_rejected() {
+        let shell = Shell::new().unwrap();
+        let dir = shell.create_temp_dir().unwrap();
+        let mut manifest = DeploymentManifest::new(L1Network::Localhost);
+        manifest.version = MANIFEST_VERSION + 1;
+        manifest.save(&shell, dir.path()).unwrap();
+        let err = DeploymentManifest::load(&shell, dir.path()).unwrap_err();
+        assert!(err.to_string().contains("has version 2"), "{err}");
+    }
+
+    #[test]
+    fn recording_unchanged_contract_is_noop() {
+        let shell = Shell::new().unwrap();
+        let dir = shell.create_temp_dir().unwrap();
+        let path = dir.path().join(MANIFEST_FILE_NAME);
+        let address = Address::repeat_byte(1);
+        let mut deployed = contract(address, H256::repeat_byte(2));
+        deployed.deploy_tx = Some(H256::repeat_byte(3));
+        deployed.block_number = Some(42);
+        DeploymentManifest::record(
+            &shell,
+            dir.path(),
+            L1Network::Localhost,
+            "Multicall3",
+            deployed.clone(),
+            false,
+        )
+        .unwrap();
+
+        // The manifest is rewritten compactly to tell if it's saved again.
+        let manifest = DeploymentManifest::load(&shell, dir.path())
+            .unwrap()
+            .unwrap();
+        let compact = serde_json::to_string(&manifest).unwrap();
+        shell.write_file(&path, &compact).unwrap();
+        DeploymentManifest::record(
+            &shell,
+            dir.path(),
+            L1Network::Localhost,
+            "Multicall3",
+            contract(address, H256::repeat_byte(2)),
+            false,
+        )
+        .unwrap();
+        assert_eq!(shell.read_file(&path).unwrap(), compact);
+
+        // Changed code is only recorded for a redeployed contract.
+        let err = DeploymentManifest::record(
+            &shell,
+            dir.path(),
+            L1Network::Localhost,
+            "Multicall3",
+            contract(address, H256::repeat_byte(9)),
+            false,
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string().contains("Multicall3 is recorded at"),
+            "{err}"
+        );
+        assert_eq!(shell.read_file(&path).unwrap(), compact);
+        DeploymentManifest::record(
+            &shell,
+            dir.path(),
+            L1Network::Localhost,
+            "Multicall3",
+            contract(address, H256::repeat_byte(9)),
+            true,
+        )
+        .unwrap();
+        let manifest = DeploymentManifest::load(&shell, dir.path())
+            .unwrap()
+            .unwrap();
+        let recorded = &manifest.contracts["Multicall3"];
+        assert_eq!(recorded.bytecode_hash, H256::repeat_byte(9));
+        assert_eq!(recorded.deploy_tx, Some(H256::repeat_byte(3)));
+    }
+
+    #[test]
+    fn recorded_address_is_only_replaced_explicitly() {
+        let shell = Shell::new().unwrap();
+        let dir = shell.create_temp_dir().unwrap();
+        let record = |address: u8, replace: bool| {
+            DeploymentManifest::record(
+                &shell,
+                dir.path(),
+                L1Network::Localhost,
+                "Verifier",
+                contract(Address::repeat_byte(address), H256::repeat_byte(2)),
+                replace,
+            )
+        };
+        record(1, false).unwrap();
+        record(1, false).unwrap();
+        record(4, false).unwrap_err();
+        let address = |shell: &Shell| {
+            DeploymentManifest::load(shell, dir.path())
+                .unwrap()
+                .unwrap()
+                .address("Verifier")
+        };
+        assert_eq!(address(&shell), Some(Address::repeat_byte(1)));
+        record(4, true).unwrap();
+        assert_eq!(address(&shell), Some(Address::repeat_byte(4)));
+    }
+}
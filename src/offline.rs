@@ -0,0 +1,335 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use web3::{
+    signing::keccak256,
+    types::{Address, Bytes, TransactionParameters, H256, U256, U64},
+};
+use xshell::Shell;
+
+use crate::{
+    init::{InitState, L1Stage},
+    signer::{check_signed_transaction, KeystoreSigner, Signer},
+    utils,
+    web3_client::Web3Client,
+};
+
+/// Transaction with all the parameters required for signing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnsignedTransaction {
+    pub description: String,
+    pub from: Address,
+    pub to: Option<Address>,
+    pub nonce: U256,
+    pub gas: U256,
+    /// Set for legacy transactions only.
+    #[serde(default)]
+    pub gas_price: Option<U256>,
+    /// Set for EIP-1559 transactions only.
+    #[serde(default)]
+    pub max_fee_per_gas: Option<U256>,
+    #[serde(default)]
+    pub max_priority_fee_per_gas: Option<U256>,
+    pub value: U256,
+    pub data: Bytes,
+    pub chain_id: u64,
+}
+
+impl UnsignedTransaction {
+    pub fn new(description: String, from: Address, tx: TransactionParameters) -> Self {
+        Self {
+            description,
+            from,
+            to: tx.to,
+            nonce: tx.nonce.expect("Transaction must be populated"),
+            gas: tx.gas,
+            gas_price: tx.gas_price,
+            max_fee_per_gas: tx.max_fee_per_gas,
+            max_priority_fee_per_gas: tx.max_priority_fee_per_gas,
+            value: tx.value,
+            data: tx.data,
+            chain_id: tx.chain_id.expect("Transaction must be populated"),
+        }
+    }
+
+    fn into_parameters(self) -> TransactionParameters {
+        let transaction_type = self.max_fee_per_gas.map(|_| U64::from(2));
+        TransactionParameters {
+            nonce: Some(self.nonce),
+            to: self.to,
+            gas: self.gas,
+            gas_price: self.gas_price,
+            max_fee_per_gas: self.max_fee_per_gas,
+            max_priority_fee_per_gas: self.max_priority_fee_per_gas,
+            transaction_type,
+            value: self.value,
+            data: self.data,
+            chain_id: Some(self.chain_id),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnsignedBatch {
+    pub hyperchain: String,
+    pub stage: L1Stage,
+    /// Unix timestamp of the moment the fees were estimated.
+    #[serde(default)]
+    pub created_at: u64,
+    pub transactions: Vec<UnsignedTransaction>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignedTransaction {
+    pub description: String,
+    pub hash: H256,
+    pub raw: Bytes,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignedBatch {
+    pub hyperchain: String,
+    pub stage: L1Stage,
+    pub transactions: Vec<SignedTransaction>,
+}
+
+/// Path to the file with unsigned transactions of the stage.
+pub fn unsigned_batch_path(hyperchain_dir: &Path, stage: L1Stage) -> PathBuf {
+    hyperchain_dir.join(format!("{stage}.unsigned.json"))
+}
+
+/// Signs the unsigned transactions with the key from the keystore. Doesn't require network access.
+pub async fn sign(file: &Path, keystore: &Path, output: Option<PathBuf>) -> anyhow::Result<()> {
+    let shell = Shell::new()?;
+    let batch: UnsignedBatch = serde_json::from_str(&shell.read_file(file)?)?;
+    let signer = KeystoreSigner::new(keystore)?;
+
+    let mut transactions = Vec::with_capacity(batch.transactions.len());
+    for tx in batch.transactions {
+        if tx.from != signer.address() {
+            anyhow::bail!(
+                "Transaction '{}' must be signed by {:?}, but the keystore has key for {:?}",
+                tx.description,
+                tx.from,
+                signer.address()
+            );
+        }
+        let description = tx.description.clone();
+        let raw = signer.sign_transaction(tx.into_parameters()).await?;
+        transactions.push(SignedTransaction {
+            description,
+            hash: H256(keccak256(&raw.0)),
+            raw,
+        });
+    }
+
+    let signed = SignedBatch {
+        hyperchain: batch.hyperchain,
+        stage: batch.stage,
+        transactions,
+    };
+    let output = output.unwrap_or_else(|| {
+        PathBuf::from(format!(
+            "{}.{}.signed.json",
+            signed.hyperchain, signed.stage
+        ))
+    });
+    shell.write_file(&output, serde_json::to_string_pretty(&signed)?)?;
+    println!(
+        "Signed {} transactions, saved to {}",
+        signed.transactions.len(),
+        output.display()
+    );
+    Ok(())
+}
+
+/// Sends the signed transactions of the stage and marks the stage as completed.
+pub async fn broadcast(name: &str, file: &Path) -> anyhow::Result<()> {
+    let shell = Shell::new()?;
+    let hyperchain_dir = utils::hyperchain_dir(name)?;
+    let mut state = InitState::load(&shell, &hyperchain_dir)?;
+    let batch: SignedBatch = serde_json::from_str(&shell.read_file(file)?)?;
+    if batch.hyperchain != name {
+        anyhow::bail!(
+            "Transactions were created for hyperchain {}",
+            batch.hyperchain
+        );
+    }
+    if state.completed_stages.contains(&batch.stage) {
+        anyhow::bail!("Stage {} is already completed", batch.stage);
+    }
+    let (Some(web3_rpc), Some(l1_network)) = (&state.web3_rpc, state.l1_network) else {
+        anyhow::bail!("Hyperchain {name} is not initialized");
+    };
+
+    let unsigned_path = unsigned_batch_path(&hyperchain_dir, batch.stage);
+    if !shell.path_exists(&unsigned_path) {
+        anyhow::bail!(
+            "Hyperchain {name} doesn't have unsigned transactions of stage {}, run `init` to create them",
+            batch.stage
+        );
+    }
+    let unsigned: UnsignedBatch = serde_json::from_str(&shell.read_file(&unsigned_path)?)?;
+    check_signed_batch(&batch, &unsigned, l1_network.chain_id())
+        .with_context(|| format!("{file:?} doesn't match the transactions in {unsigned_path:?}"))?;
+
+    let client = Web3Client::new(web3_rpc, l1_network)
+        .await?
+        .for_hyperchain(&hyperchain_dir)?
+        .in_stage(batch.stage);
+    for tx in batch.transactions {
+        // Transactions may have been partially sent by a previous `broadcast` run.
+        if let Some(receipt) = client.receipt(tx.hash).await? {
+            if receipt.status != Some(1.into()) {
+                anyhow::bail!(
+                    "Transaction '{}' ({:?}) has failed",
+                    tx.description,
+                    tx.hash
+                );
+            }
+            println!("Already sent: {} ({:?})", tx.description, tx.hash);
+            continue;
+        }
+        let receipt = client.broadcast(&tx.description, tx.raw).await?;
+        println!("Sent: {} ({:?})", tx.description, receipt.transaction_hash);
+    }
+
+    state.completed_stages.insert(batch.stage);
+    state.save(&shell, &hyperchain_dir)?;
+    shell.remove_path(unsigned_batch_path(&hyperchain_dir, batch.stage))?;
+    println!(
+        "Stage {} is completed, run `init` again to continue",
+        batch.stage
+    );
+    Ok(())
+}
+
+/// Makes sure that the signed transactions are exactly the exported ones, signed by
+/// the expected account for the L1 network of the hyperchain.
+fn check_signed_batch(
+    signed: &SignedBatch,
+    unsigned: &UnsignedBatch,
+    chain_id: u64,
+) -> anyhow::Result<()> {
+    if signed.stage != unsigned.stage {
+        anyhow::bail!(
+            "Transactions are signed for stage {} instead of {}",
+            signed.stage,
+            unsigned.stage
+        );
+    }
+    if signed.transactions.len() != unsigned.transactions.len() {
+        anyhow::bail!(
+            "{} transactions are signed instead of {}",
+            signed.transactions.len(),
+            unsigned.transactions.len()
+        );
+    }
+    for (signed, unsigned) in signed.transactions.iter().zip(&unsigned.transactions) {
+        if unsigned.chain_id != chain_id {
+            anyhow::bail!(
+                "Transaction '{}' is created for chain ID {} instead of {chain_id}",
+                unsigned.description,
+                unsigned.chain_id
+            );
+        }
+        if signed.hash != H256(keccak256(&signed.raw.0)) {
+            anyhow::bail!("Transaction '{}' has invalid hash", signed.description);
+        }
+        check_signed_transaction(
+            &signed.raw.0,
+            unsigned.from,
+            &unsigned.clone().into_parameters(),
+        )
+        .with_context(|| format!("Transaction '{}'", unsigned.description))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signer::LocalSigner;
+
+    async fn batches(chain_id: u64) -> (SignedBatch, UnsignedBatch) {
+        let signer = LocalSigner::new(H256::repeat_byte(0x42)).unwrap();
+        let transactions: Vec<_> = (0..2)
+            .map(|nonce| UnsignedTransaction {
+                description: format!("Transaction {nonce}"),
+                from: signer.address(),
+                to: Some(Address::repeat_byte(0x11)),
+                nonce: nonce.into(),
+                gas: 100_000.into(),
+                gas_price: None,
+                max_fee_per_gas: Some(30_000_000_000u64.into()),
+                max_priority_fee_per_gas: Some(1_000_000_000u64.into()),
+                value: U256::zero(),
+                data: vec![0xab, 0xcd].into(),
+                chain_id,
+            })
+            .collect();
+        let mut signed = Vec::new();
+        for tx in &transactions {
+            let raw = signer
+                .sign_transaction(tx.clone().into_parameters())
+                .await
+                .unwrap();
+            signed.push(SignedTransaction {
+                description: tx.description.clone(),
+                hash: H256(keccak256(&raw.0)),
+                raw,
+            });
+        }
+        let signed = SignedBatch {
+            hyperchain: "hyperchain".to_string(),
+            stage: L1Stage::DeployVerifier,
+            transactions: signed,
+        };
+        let unsigned = UnsignedBatch {
+            hyperchain: "hyperchain".to_string(),
+            stage: L1Stage::DeployVerifier,
+            created_at: 0,
+            transactions,
+        };
+        (signed, unsigned)
+    }
+
+    #[tokio::test]
+    async fn signed_batch_must_match_unsigned_one() {
+        let (signed, mut unsigned) = batches(11_155_111).await;
+        check_signed_batch(&signed, &unsigned, 11_155_111).unwrap();
+
+        let err = check_signed_batch(&signed, &unsigned, 9).unwrap_err();
+        assert!(err.to_string().contains("instead of 9"), "{err}");
+
+        unsigned.transactions[1].data = vec![0xff].into();
+        let err = check_signed_batch(&signed, &unsigned, 11_155_111).unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "Transaction 'Transaction 1': Signed transaction has different data"
+        );
+
+        unsigned.transactions.pop();
+        let err = check_signed_batch(&signed, &unsigned, 11_155_111).unwrap_err();
+        assert_eq!(err.to_string(), "2 transactions are signed instead of 1");
+    }
+
+    #[tokio::test]
+    async fn signed_batch_for_another_network_is_rejected() {
+        // Signed consistently, but for another network than the hyperchain uses.
+        let (signed, unsigned) = batches(9).await;
+        check_signed_batch(&signed, &unsigned, 11_155_111).unwrap_err();
+
+        let (mut signed, unsigned) = batches(11_155_111).await;
+        let (other, _) = batches(9).await;
+        signed.transactions[0].raw = other.transactions[0].raw.clone();
+        signed.transactions[0].hash = other.transactions[0].hash;
+        let err = check_signed_batch(&signed, &unsigned, 11_155_111).unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "Transaction 'Transaction 0': Signed transaction has different chain ID"
+        );
+    }
+}
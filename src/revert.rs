@@ -0,0 +1,141 @@
+use web3::ethabi::{self, ParamType, Token};
+
+/// Selector of `Error(string)` used by `revert("...")` and `require(..., "...")`.
+const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+/// Selector of `Panic(uint256)` used by failed assertions, overflows and so on.
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// Extracts the revert data from the error returned by `eth_call` or `eth_estimateGas`.
+pub fn revert_data(err: &web3::Error) -> Option<Vec<u8>> {
+    let web3::Error::Rpc(err) = err else {
+        return None;
+    };
+    match err.data.as_ref().and_then(|data| data.as_str()) {
+        Some(data) => hex::decode(data.trim_start_matches("0x")).ok(),
+        // Reverts without a reason don't have any data.
+        None if err.message.contains("revert") => Some(Vec::new()),
+        None => None,
+    }
+}
+
+/// Human-readable reason of the revert. Custom errors are looked up in the contract ABI.
+pub fn decode_revert(data: &[u8], abi: Option<&ethabi::Contract>) -> String {
+    if data.is_empty() {
+        return "reverted without a reason".to_string();
+    }
+    if data.len() < 4 {
+        return format!("reverted with malformed data 0x{}", hex::encode(data));
+    }
+    let (selector, args) = data.split_at(4);
+    if selector == ERROR_SELECTOR {
+        if let Ok(tokens) = ethabi::decode(&[ParamType::String], args) {
+            if let Some(Token::String(reason)) = tokens.into_iter().next() {
+                return format!("reverted with '{reason}'");
+            }
+        }
+    }
+    if selector == PANIC_SELECTOR {
+        if let Ok(tokens) = ethabi::decode(&[ParamType::Uint(256)], args) {
+            if let Some(Token::Uint(code)) = tokens.into_iter().next() {
+                return format!(
+                    "panicked with code {code:#x} ({})",
+                    panic_reason(code.low_u64())
+                );
+            }
+        }
+    }
+    for error in abi.into_iter().flat_map(|abi| abi.errors()) {
+        if error.signature()[..4] != *selector {
+            continue;
+        }
+        if let Ok(tokens) = error.decode(args) {
+            let args: Vec<_> = tokens.iter().map(ToString::to_string).collect();
+            return format!("reverted with {}({})", error.name, args.join(", "));
+        }
+    }
+    format!("reverted with unknown error 0x{}", hex::encode(data))
+}
+
+fn panic_reason(code: u64) -> &'static str {
+    match code {
+        0x01 => "assertion failed",
+        0x11 => "arithmetic overflow or underflow",
+        0x12 => "division or modulo by zero",
+        0x21 => "invalid enum value",
+        0x22 => "invalid storage byte array",
+        0x31 => "pop on empty array",
+        0x32 => "array index out of bounds",
+        0x41 => "too much memory allocated",
+        0x51 => "call to uninitialized function",
+        _ => "unknown panic",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_call(selector: &[u8], tokens: &[Token]) -> Vec<u8> {
+        [selector, &ethabi::encode(tokens)].concat()
+    }
+
+    #[test]
+    fn error_string_is_decoded() {
+        let data = encode_call(
+            &ERROR_SELECTOR,
+            &[Token::String(
+                "Ownable: caller is not the owner".to_string(),
+            )],
+        );
+        assert_eq!(
+            decode_revert(&data, None),
+            "reverted with 'Ownable: caller is not the owner'"
+        );
+    }
+
+    #[test]
+    fn panic_is_decoded() {
+        let data = encode_call(&PANIC_SELECTOR, &[Token::Uint(0x11.into())]);
+        assert_eq!(
+            decode_revert(&data, None),
+            "panicked with code 0x11 (arithmetic overflow or underflow)"
+        );
+    }
+
+    #[test]
+    fn custom_error_is_decoded_with_abi() {
+        let abi: ethabi::Contract = serde_json::from_value(serde_json::json!([{
+            "type": "error",
+            "name": "Unauthorized",
+            "inputs": [{ "name": "caller", "type": "address" }]
+        }]))
+        .unwrap();
+        let error = abi.error("Unauthorized").unwrap();
+        let caller = ethabi::Address::repeat_byte(0xab);
+        let data = encode_call(&error.signature()[..4], &[Token::Address(caller)]);
+        assert_eq!(
+            decode_revert(&data, Some(&abi)),
+            format!("reverted with Unauthorized({})", hex::encode(caller))
+        );
+        // Without the ABI, the error can't be decoded.
+        assert!(decode_revert(&data, None).starts_with("reverted with unknown error 0x"));
+    }
+
+    #[test]
+    fn undecodable_data_is_reported() {
+        assert_eq!(decode_revert(&[], None), "reverted without a reason");
+        assert_eq!(
+            decode_revert(&[0x12, 0x34], None),
+            "reverted with malformed data 0x1234"
+        );
+        assert_eq!(
+            decode_revert(&[0xde, 0xad, 0xbe, 0xef, 0x01], None),
+            "reverted with unknown error 0xdeadbeef01"
+        );
+        // Selector of `Error(string)` with the data that is not a string.
+        assert_eq!(
+            decode_revert(&ERROR_SELECTOR, None),
+            "reverted with unknown error 0x08c379a0"
+        );
+    }
+}
@@ -0,0 +1,545 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use rlp::{Rlp, RlpStream};
+use serde_json::json;
+use web3::{
+    signing::{keccak256, recover, Key, SecretKey, SecretKeyRef, Signature},
+    transports::Http,
+    types::{Address, Bytes, TransactionParameters, H256, U256, U64},
+    Transport,
+};
+
+/// Signs transactions on behalf of a single account.
+///
+/// Transactions passed to the signer are fully populated (nonce, gas, fees and chain ID),
+/// so signers never need to talk to the L1 network.
+#[async_trait]
+pub trait Signer: Send + Sync {
+    fn address(&self) -> Address;
+
+    /// Returns the raw signed transaction, ready to be broadcast.
+    async fn sign_transaction(&self, tx: TransactionParameters) -> anyhow::Result<Bytes>;
+}
+
+/// Signer with the private key kept in memory.
+pub struct LocalSigner {
+    key: SecretKey,
+}
+
+impl LocalSigner {
+    pub fn new(pk: H256) -> anyhow::Result<Self> {
+        let key = SecretKey::from_slice(pk.as_bytes()).context("Invalid private key")?;
+        Ok(Self { key })
+    }
+}
+
+#[async_trait]
+impl Signer for LocalSigner {
+    fn address(&self) -> Address {
+        SecretKeyRef::new(&self.key).address()
+    }
+
+    async fn sign_transaction(&self, tx: TransactionParameters) -> anyhow::Result<Bytes> {
+        sign_locally(tx, &self.key)
+    }
+}
+
+/// Signer with the private key stored in a keystore file. The key is only decrypted
+/// for the time of signing.
+pub struct KeystoreSigner {
+    path: PathBuf,
+    address: Address,
+}
+
+impl KeystoreSigner {
+    pub fn new(path: &Path) -> anyhow::Result<Self> {
+        let pk = crate::wallets::decrypt_keystore(path)?;
+        Ok(Self {
+            path: path.to_owned(),
+            address: crate::web3_client::address(pk),
+        })
+    }
+}
+
+#[async_trait]
+impl Signer for KeystoreSigner {
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    async fn sign_transaction(&self, tx: TransactionParameters) -> anyhow::Result<Bytes> {
+        let pk = crate::wallets::decrypt_keystore(&self.path)?;
+        let key = SecretKey::from_slice(pk.as_bytes()).context("Invalid private key")?;
+        sign_locally(tx, &key)
+    }
+}
+
+/// Signer delegating to a remote JSON-RPC signer (e.g. web3signer) via `eth_signTransaction`,
+/// so that the key is never exposed to the wizard.
+pub struct RemoteSigner {
+    url: String,
+    transport: Http,
+    address: Address,
+}
+
+impl RemoteSigner {
+    pub fn new(url: &str, address: Address) -> anyhow::Result<Self> {
+        Ok(Self {
+            url: url.to_string(),
+            transport: Http::new(url)?,
+            address,
+        })
+    }
+}
+
+#[async_trait]
+impl Signer for RemoteSigner {
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    async fn sign_transaction(&self, tx: TransactionParameters) -> anyhow::Result<Bytes> {
+        let mut request = json!({
+            "from": self.address,
+            "to": tx.to,
+            "gas": tx.gas,
+            "value": tx.value,
+            "data": tx.data,
+            "nonce": tx.nonce,
+            "chainId": tx.chain_id.map(U64::from),
+        });
+        if let Some(gas_price) = tx.gas_price {
+            request["gasPrice"] = json!(gas_price);
+        }
+        if let Some(max_fee_per_gas) = tx.max_fee_per_gas {
+            request["maxFeePerGas"] = json!(max_fee_per_gas);
+        }
+        if let Some(max_priority_fee_per_gas) = tx.max_priority_fee_per_gas {
+            request["maxPriorityFeePerGas"] = json!(max_priority_fee_per_gas);
+        }
+        if let Some(transaction_type) = tx.transaction_type {
+            request["type"] = json!(transaction_type);
+        }
+
+        let response = self
+            .transport
+            .execute("eth_signTransaction", vec![request])
+            .await
+            .with_context(|| format!("Remote signer {} failed to sign", self.url))?;
+        // Geth-like signers respond with `{ raw, tx }`, web3signer responds with the raw bytes.
+        let raw = match response.get("raw") {
+            Some(raw) => raw.clone(),
+            None => response,
+        };
+        let raw: Bytes =
+            serde_json::from_value(raw).context("Unexpected response from the remote signer")?;
+        check_signed_transaction(&raw.0, self.address, &tx)
+            .with_context(|| format!("Remote signer {} signed another transaction", self.url))?;
+        Ok(raw)
+    }
+}
+
+/// Transaction decoded from its raw signed form.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedTransaction {
+    /// Recovered from the signature.
+    pub from: Address,
+    /// Not set for the transactions without replay protection.
+    pub chain_id: Option<u64>,
+    pub nonce: U256,
+    pub to: Option<Address>,
+    pub value: U256,
+    pub data: Vec<u8>,
+    pub gas: U256,
+    /// Gas price for legacy transactions.
+    pub max_fee_per_gas: U256,
+}
+
+impl SignedTransaction {
+    /// Decodes the legacy or EIP-1559 transaction and recovers its sender.
+    pub fn decode(raw: &[u8]) -> anyhow::Result<Self> {
+        let eip1559 = raw.first() == Some(&(EIP1559_TX_TYPE as u8));
+        let rlp = Rlp::new(if eip1559 { &raw[1..] } else { raw });
+        let (fields, unsigned_fields) = if eip1559 { (12, 9) } else { (9, 6) };
+        if rlp.item_count()? != fields {
+            anyhow::bail!("Transaction has unexpected number of fields");
+        }
+        // Legacy transactions start with the nonce, EIP-1559 ones have the chain ID and
+        // the priority fee in front of the common fields.
+        let (nonce, fee, common) = if eip1559 { (1, 3, 4) } else { (0, 1, 2) };
+        let to: Vec<u8> = rlp.val_at(common + 1)?;
+        let to = match to.len() {
+            0 => None,
+            20 => Some(Address::from_slice(&to)),
+            _ => anyhow::bail!("Transaction has invalid recipient"),
+        };
+        let v = decode_u256(&rlp, fields - 3)?;
+        let mut signature = [0; 64];
+        decode_u256(&rlp, fields - 2)?.to_big_endian(&mut signature[..32]);
+        decode_u256(&rlp, fields - 1)?.to_big_endian(&mut signature[32..]);
+
+        let mut stream = RlpStream::new();
+        let (chain_id, recovery_id) = if eip1559 {
+            stream.begin_list(unsigned_fields);
+            (Some(decode_u256(&rlp, 0)?.as_u64()), v.as_u64())
+        } else if v >= 35.into() {
+            // EIP-155: `v = chain_id * 2 + 35 + recovery_id`.
+            let chain_id = (v - 35) / 2;
+            stream.begin_list(unsigned_fields + 3);
+            (Some(chain_id.as_u64()), (v - 35).as_u64() % 2)
+        } else {
+            stream.begin_list(unsigned_fields);
+            (None, v.as_u64().wrapping_sub(27))
+        };
+        for i in 0..unsigned_fields {
+            stream.append_raw(rlp.at(i)?.as_raw(), 1);
+        }
+        if let (Some(chain_id), false) = (chain_id, eip1559) {
+            stream.append(&chain_id);
+            stream.append(&0u8);
+            stream.append(&0u8);
+        }
+        let payload = stream.out().to_vec();
+        let hash = if eip1559 {
+            keccak256(&[&[EIP1559_TX_TYPE as u8], payload.as_slice()].concat())
+        } else {
+            keccak256(&payload)
+        };
+        if recovery_id > 1 {
+            anyhow::bail!("Transaction has invalid signature");
+        }
+        let from = recover(&hash, &signature, recovery_id as i32)
+            .map_err(|_| anyhow::anyhow!("Transaction has invalid signature"))?;
+
+        Ok(Self {
+            from,
+            chain_id,
+            nonce: decode_u256(&rlp, nonce)?,
+            to,
+            value: decode_u256(&rlp, common + 2)?,
+            data: rlp.val_at(common + 3)?,
+            gas: decode_u256(&rlp, common)?,
+            max_fee_per_gas: decode_u256(&rlp, fee)?,
+        })
+    }
+}
+
+fn decode_u256(rlp: &Rlp, index: usize) -> anyhow::Result<U256> {
+    let bytes: Vec<u8> = rlp.val_at(index)?;
+    if bytes.len() > 32 {
+        anyhow::bail!("Transaction field {index} is too long");
+    }
+    Ok(U256::from_big_endian(&bytes))
+}
+
+/// Makes sure that the transaction signed outside of the wizard is the requested one,
+/// signed by the expected account.
+pub fn check_signed_transaction(
+    raw: &[u8],
+    from: Address,
+    tx: &TransactionParameters,
+) -> anyhow::Result<()> {
+    let signed = SignedTransaction::decode(raw)?;
+    let max_fee_per_gas = tx.max_fee_per_gas.or(tx.gas_price);
+    let mut mismatches = Vec::new();
+    for (field, matches) in [
+        ("sender", signed.from == from),
+        ("chain ID", signed.chain_id == tx.chain_id),
+        ("nonce", Some(signed.nonce) == tx.nonce),
+        ("recipient", signed.to == tx.to),
+        ("value", signed.value == tx.value),
+        ("data", signed.data == tx.data.0),
+        ("gas limit", signed.gas == tx.gas),
+        ("fee", Some(signed.max_fee_per_gas) == max_fee_per_gas),
+    ] {
+        if !matches {
+            mismatches.push(field);
+        }
+    }
+    if !mismatches.is_empty() {
+        anyhow::bail!("Signed transaction has different {}", mismatches.join(", "));
+    }
+    Ok(())
+}
+
+/// Type of EIP-1559 transactions, the only typed transactions the wizard sends.
+const EIP1559_TX_TYPE: u64 = 2;
+
+/// Signs the fully populated transaction without any access to L1.
+fn sign_locally(tx: TransactionParameters, key: &SecretKey) -> anyhow::Result<Bytes> {
+    let (Some(nonce), Some(chain_id)) = (tx.nonce, tx.chain_id) else {
+        anyhow::bail!("Transaction must be fully populated before signing");
+    };
+    let eip1559 = match tx.transaction_type.map(|tx_type| tx_type.as_u64()) {
+        None | Some(0) => false,
+        Some(EIP1559_TX_TYPE) => true,
+        Some(tx_type) => anyhow::bail!("Unsupported transaction type {tx_type}"),
+    };
+    let fees = if eip1559 {
+        tx.max_fee_per_gas.zip(tx.max_priority_fee_per_gas)
+    } else {
+        tx.gas_price.map(|gas_price| (gas_price, gas_price))
+    };
+    let Some((max_fee_per_gas, max_priority_fee_per_gas)) = fees else {
+        anyhow::bail!("Transaction must be fully populated before signing");
+    };
+
+    let encode = |signature: Option<&Signature>| {
+        let mut stream = RlpStream::new();
+        if eip1559 {
+            stream.begin_list(if signature.is_some() { 12 } else { 9 });
+            stream.append(&chain_id);
+            stream.append(&nonce);
+            stream.append(&max_priority_fee_per_gas);
+            stream.append(&max_fee_per_gas);
+        } else {
+            stream.begin_list(9);
+            stream.append(&nonce);
+            stream.append(&max_fee_per_gas);
+        }
+        stream.append(&tx.gas);
+        match tx.to {
+            Some(to) => stream.append(&to),
+            None => stream.append(&""),
+        };
+        stream.append(&tx.value);
+        stream.append(&tx.data.0);
+        if eip1559 {
+            let access_list = tx.access_list.as_deref().unwrap_or_default();
+            stream.begin_list(access_list.len());
+            for item in access_list {
+                stream.begin_list(2);
+                stream.append(&item.address);
+                stream.append_list(&item.storage_keys);
+            }
+        }
+        match signature {
+            Some(signature) => {
+                stream.append(&signature.v);
+                stream.append(&U256::from_big_endian(signature.r.as_bytes()));
+                stream.append(&U256::from_big_endian(signature.s.as_bytes()));
+            }
+            // EIP-155 replay protection.
+            None if !eip1559 => {
+                stream.append(&chain_id);
+                stream.append(&0u8);
+                stream.append(&0u8);
+            }
+            None => {}
+        }
+        let payload = stream.out().to_vec();
+        if eip1559 {
+            [&[EIP1559_TX_TYPE as u8], payload.as_slice()].concat()
+        } else {
+            payload
+        }
+    };
+
+    let hash = keccak256(&encode(None));
+    let key = SecretKeyRef::new(key);
+    let signature = if eip1559 {
+        key.sign_message(&hash)?
+    } else {
+        key.sign(&hash, Some(chain_id))?
+    };
+    Ok(encode(Some(&signature)).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+    use web3::{
+        api::{Accounts, Namespace},
+        types::U64,
+    };
+
+    use super::*;
+
+    fn key() -> SecretKey {
+        SecretKey::from_slice(&[0x42; 32]).unwrap()
+    }
+
+    fn transaction(transaction_type: Option<u64>) -> TransactionParameters {
+        let mut tx = TransactionParameters {
+            nonce: Some(7.into()),
+            to: Some(Address::repeat_byte(0x11)),
+            gas: 21_000.into(),
+            value: 1_000_000.into(),
+            data: vec![0xab, 0xcd].into(),
+            chain_id: Some(11_155_111),
+            transaction_type: transaction_type.map(U64::from),
+            ..Default::default()
+        };
+        match transaction_type {
+            Some(EIP1559_TX_TYPE) => {
+                tx.max_fee_per_gas = Some(30_000_000_000u64.into());
+                tx.max_priority_fee_per_gas = Some(1_000_000_000u64.into());
+            }
+            _ => tx.gas_price = Some(20_000_000_000u64.into()),
+        }
+        tx
+    }
+
+    #[tokio::test]
+    async fn local_signing_matches_web3() {
+        // Unreachable URL: all the parameters are provided, so web3 never uses the transport.
+        let accounts = Accounts::new(Http::new("http://127.0.0.1:1").unwrap());
+        for transaction_type in [None, Some(EIP1559_TX_TYPE)] {
+            let tx = transaction(transaction_type);
+            let expected = accounts.sign_transaction(tx.clone(), &key()).await.unwrap();
+            let raw = sign_locally(tx, &key()).unwrap();
+            assert_eq!(raw, expected.raw_transaction, "{transaction_type:?}");
+        }
+    }
+
+    #[test]
+    fn local_signing_requires_populated_transaction() {
+        let mut tx = transaction(Some(EIP1559_TX_TYPE));
+        tx.max_fee_per_gas = None;
+        assert!(sign_locally(tx, &key()).is_err());
+    }
+
+    #[test]
+    fn signed_transactions_are_decoded() {
+        let from = SecretKeyRef::new(&key()).address();
+        for transaction_type in [None, Some(EIP1559_TX_TYPE)] {
+            let mut tx = transaction(transaction_type);
+            let raw = sign_locally(tx.clone(), &key()).unwrap();
+            let signed = SignedTransaction::decode(&raw.0).unwrap();
+            assert_eq!(signed.from, from, "{transaction_type:?}");
+            assert_eq!(signed.chain_id, Some(11_155_111));
+            assert_eq!(signed.nonce, 7.into());
+            assert_eq!(signed.to, tx.to);
+            assert_eq!(signed.data, vec![0xab, 0xcd]);
+            check_signed_transaction(&raw.0, from, &tx).unwrap();
+
+            tx.value += 1.into();
+            tx.to = None;
+            let err = check_signed_transaction(&raw.0, Address::zero(), &tx).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "Signed transaction has different sender, recipient, value"
+            );
+        }
+
+        let mut tx = transaction(None);
+        tx.to = None;
+        let raw = sign_locally(tx, &key()).unwrap();
+        let signed = SignedTransaction::decode(&raw.0).unwrap();
+        assert_eq!((signed.from, signed.to), (from, None));
+        SignedTransaction::decode(&raw.0[..raw.0.len() - 1]).unwrap_err();
+
+        // Keyless deployment, without replay protection.
+        let factory = crate::keyless::singleton_factory();
+        let signed = SignedTransaction::decode(&factory.raw_tx().0).unwrap();
+        assert_eq!(signed.from, factory.deployer);
+        assert_eq!((signed.chain_id, signed.to), (None, None));
+    }
+
+    /// Serves a single `eth_signTransaction` request, signing it with `key()`.
+    /// With `tamper`, the value of the signed transaction is changed.
+    async fn mock_signer(listener: TcpListener, tamper: bool) -> Value {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let body = loop {
+            let mut buf = [0; 4096];
+            let read = stream.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..read]);
+            let text = String::from_utf8_lossy(&request);
+            let Some((headers, body)) = text.split_once("\r\n\r\n") else {
+                continue;
+            };
+            let content_length: usize = headers
+                .lines()
+                .find_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    name.eq_ignore_ascii_case("content-length")
+                        .then(|| value.trim().parse().unwrap())
+                })
+                .unwrap();
+            if body.len() >= content_length {
+                break body.to_string();
+            }
+        };
+        let request: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(request["method"], "eth_signTransaction");
+
+        let params = &request["params"][0];
+        let tx = TransactionParameters {
+            nonce: serde_json::from_value(params["nonce"].clone()).unwrap(),
+            to: serde_json::from_value(params["to"].clone()).unwrap(),
+            gas: serde_json::from_value(params["gas"].clone()).unwrap(),
+            value: serde_json::from_value(params["value"].clone()).unwrap(),
+            data: serde_json::from_value(params["data"].clone()).unwrap(),
+            chain_id: serde_json::from_value::<U64>(params["chainId"].clone())
+                .ok()
+                .map(|chain_id| chain_id.as_u64()),
+            transaction_type: serde_json::from_value(params["type"].clone()).unwrap(),
+            gas_price: serde_json::from_value(params["gasPrice"].clone()).unwrap(),
+            max_fee_per_gas: serde_json::from_value(params["maxFeePerGas"].clone()).unwrap(),
+            max_priority_fee_per_gas: serde_json::from_value(
+                params["maxPriorityFeePerGas"].clone(),
+            )
+            .unwrap(),
+            ..Default::default()
+        };
+        let mut tx = tx;
+        if tamper {
+            tx.value += 1.into();
+        }
+        let raw = sign_locally(tx, &key()).unwrap();
+        let response = json!({
+            "jsonrpc": "2.0",
+            "id": request["id"],
+            "result": { "raw": raw, "tx": {} },
+        })
+        .to_string();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{response}",
+            response.len()
+        );
+        stream.write_all(response.as_bytes()).await.unwrap();
+        params.clone()
+    }
+
+    #[tokio::test]
+    async fn remote_signer_round_trip() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(mock_signer(listener, false));
+
+        let address = SecretKeyRef::new(&key()).address();
+        let signer = RemoteSigner::new(&url, address).unwrap();
+        let tx = transaction(Some(EIP1559_TX_TYPE));
+        let raw = signer.sign_transaction(tx.clone()).await.unwrap();
+        let params = server.await.unwrap();
+
+        assert_eq!(params["from"], json!(address));
+        assert_eq!(raw, sign_locally(tx, &key()).unwrap());
+    }
+
+    #[tokio::test]
+    async fn remote_signer_tampering_is_detected() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(mock_signer(listener, true));
+
+        let address = SecretKeyRef::new(&key()).address();
+        let signer = RemoteSigner::new(&url, address).unwrap();
+        let err = signer
+            .sign_transaction(transaction(Some(EIP1559_TX_TYPE)))
+            .await
+            .unwrap_err();
+        server.await.unwrap();
+        assert_eq!(
+            format!("{err:#}"),
+            format!("Remote signer {url} signed another transaction: Signed transaction has different value")
+        );
+    }
+}
@@ -1,9 +1,17 @@
-use std::path::PathBuf;
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
 
 use anyhow::Context;
 use directories::ProjectDirs;
+use web3::{
+    signing::keccak256,
+    types::{Address, U256},
+};
+use xshell::Shell;
 
-use crate::consts;
+use crate::{consts, init::STATE_FILE_NAME};
 
 pub fn base_dir() -> anyhow::Result<PathBuf> {
     let project_dirs =
@@ -15,3 +23,178 @@ pub fn base_dir() -> anyhow::Result<PathBuf> {
 pub fn hyperchain_dir(name: &str) -> anyhow::Result<PathBuf> {
     base_dir().map(|d| d.join(name))
 }
+
+/// Names of all the hyperchains in the workspace.
+pub fn hyperchains(shell: &Shell) -> anyhow::Result<Vec<String>> {
+    let base_dir = base_dir()?;
+    if !shell.path_exists(&base_dir) {
+        return Ok(Vec::new());
+    }
+    let mut names = Vec::new();
+    for path in shell.read_dir(&base_dir)? {
+        if !shell.path_exists(path.join(STATE_FILE_NAME)) {
+            continue;
+        }
+        if let Some(name) = path.file_name() {
+            names.push(name.to_string_lossy().to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Recursively copies the contents of `from` directory into `to` directory.
+pub fn copy_dir_all(shell: &Shell, from: &Path, to: &Path) -> anyhow::Result<()> {
+    shell.create_dir(to)?;
+    for path in shell.read_dir(from)? {
+        let destination = to.join(path.file_name().context("Unexpected path")?);
+        if path.is_dir() {
+            copy_dir_all(shell, &path, &destination)?;
+        } else {
+            shell.copy_file(&path, &destination)?;
+        }
+    }
+    Ok(())
+}
+
+/// Formats the amount in wei as ETH with 3 decimal places.
+pub fn format_ether(amount: U256) -> String {
+    let one_eth = U256::from(10).pow(18.into());
+    let (eth, rest) = amount.div_mod(one_eth);
+    // `U256` doesn't support the width in formatting, while the rest always fits in `u64`.
+    let rest = (rest / U256::from(10).pow(15.into())).as_u64();
+    format!("{eth}.{rest:03}")
+}
+
+/// Parses the decimal amount of ETH into wei.
+pub fn parse_ether(amount: &str) -> anyhow::Result<U256> {
+    let invalid = || anyhow::anyhow!("Invalid amount: {amount}");
+    let (int, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    if int.is_empty() && fraction.is_empty() {
+        return Err(invalid());
+    }
+    if !(int.chars().chain(fraction.chars())).all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    if fraction.len() > 18 {
+        anyhow::bail!("Amount {amount} has too many decimal places");
+    }
+    let int = U256::from_dec_str(if int.is_empty() { "0" } else { int }).map_err(|_| invalid())?;
+    let fraction = U256::from_dec_str(&format!("{fraction:0<18}")).map_err(|_| invalid())?;
+    int.checked_mul(U256::exp10(18))
+        .and_then(|wei| wei.checked_add(fraction))
+        .with_context(|| format!("Amount {amount} is too large"))
+}
+
+/// Formats the address with the EIP-55 mixed-case checksum.
+pub fn to_checksum(address: Address) -> String {
+    let hex = hex::encode(address);
+    let hash = keccak256(hex.as_bytes());
+    let checksummed: String = hex
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let nibble = if i % 2 == 0 {
+                hash[i / 2] >> 4
+            } else {
+                hash[i / 2] & 0xf
+            };
+            if nibble >= 8 {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        })
+        .collect();
+    format!("0x{checksummed}")
+}
+
+/// Converts a contract name to an identifier, e.g. `DiamondProxy` to `DIAMOND_PROXY`.
+pub fn screaming_snake_case(name: &str) -> String {
+    let mut result = String::new();
+    let mut prev_lowercase = false;
+    for c in name.chars() {
+        if c.is_ascii_uppercase() && prev_lowercase {
+            result.push('_');
+        }
+        prev_lowercase = c.is_ascii_lowercase() || c.is_ascii_digit();
+        result.push(if c.is_ascii_alphanumeric() {
+            c.to_ascii_uppercase()
+        } else {
+            '_'
+        });
+    }
+    result
+}
+
+/// Restores the contract name from the identifier made by `screaming_snake_case`. The case
+/// of acronyms is only restored for the known L1 contracts, e.g. `L1_ERC20_BRIDGE` is
+/// `L1ERC20Bridge`, and the rest are converted to Pascal case.
+pub fn contract_name(identifier: &str) -> String {
+    if let Some(name) = consts::L1_CONTRACT_NAMES
+        .iter()
+        .find(|name| screaming_snake_case(name) == identifier)
+    {
+        return name.to_string();
+    }
+    identifier
+        .split('_')
+        .map(|word| {
+            let word = word.to_lowercase();
+            let mut chars = word.chars();
+            chars.next().map_or(String::new(), |first| {
+                first.to_uppercase().chain(chars).collect()
+            })
+        })
+        .collect()
+}
+
+/// Current Unix timestamp, in seconds.
+pub fn unix_timestamp() -> anyhow::Result<u64> {
+    Ok(SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ether_amounts_are_parsed() {
+        let wei = |amount: u128| U256::from(amount);
+        assert_eq!(parse_ether("1").unwrap(), wei(10u128.pow(18)));
+        assert_eq!(parse_ether("1.5").unwrap(), wei(15 * 10u128.pow(17)));
+        assert_eq!(parse_ether(".25").unwrap(), wei(25 * 10u128.pow(16)));
+        assert_eq!(parse_ether("2.").unwrap(), wei(2 * 10u128.pow(18)));
+        assert_eq!(parse_ether("0.000000000000000001").unwrap(), wei(1));
+
+        let err = parse_ether("0.0000000000000000001").unwrap_err();
+        assert!(err.to_string().contains("too many decimal places"), "{err}");
+        for amount in ["", ".", "-1", "1.-5", "+1", "1e18", "1,5", " 1"] {
+            assert_eq!(
+                parse_ether(amount).unwrap_err().to_string(),
+                format!("Invalid amount: {amount}")
+            );
+        }
+        let err = parse_ether(&U256::MAX.to_string()).unwrap_err();
+        assert!(err.to_string().contains("too large"), "{err}");
+    }
+
+    #[test]
+    fn ether_amounts_are_formatted() {
+        assert_eq!(format_ether(U256::zero()), "0.000");
+        assert_eq!(format_ether(U256::exp10(18)), "1.000");
+        // Amounts are truncated, not rounded.
+        assert_eq!(format_ether(U256::exp10(18) * 2 - 1), "1.999");
+        assert_eq!(format_ether(U256::exp10(14)), "0.000");
+        assert_eq!(format_ether(U256::exp10(16) * 5), "0.050");
+        for amount in ["0.001", "1.5", "12.345", "1000000"] {
+            let formatted = format_ether(parse_ether(amount).unwrap());
+            assert_eq!(
+                parse_ether(&formatted).unwrap(),
+                parse_ether(amount).unwrap()
+            );
+        }
+    }
+}
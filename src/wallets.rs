@@ -0,0 +1,688 @@
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::OnceLock,
+};
+
+use anyhow::Context;
+use bip32::{DerivationPath, XPrv};
+use bip39::Mnemonic;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use web3::{
+    signing::SecretKey,
+    types::{Address, H256},
+};
+use xshell::Shell;
+
+use crate::{
+    consts::localhost_rich_wallet,
+    init::InitState,
+    signer::{KeystoreSigner, LocalSigner, RemoteSigner, Signer},
+    utils,
+    web3_client::Web3Client,
+    L1Network, PrerequisiteContracts,
+};
+
+/// Environment variable with the passphrase for the hyperchain keystores.
+/// If not set, the passphrase is prompted.
+const PASSPHRASE_ENV: &str = "ZK_STACK_WIZARD_PASSPHRASE";
+const KEYSTORE_DIR_NAME: &str = "keystore";
+
+static PASSPHRASE: OnceLock<String> = OnceLock::new();
+
+/// Derivation path used by default for keys derived from a mnemonic.
+const DEFAULT_DERIVATION_PATH: &str = "m/44'/60'/0'/0/0";
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, ValueEnum,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum WalletRole {
+    Admin,
+    Operator,
+    BlobOperator,
+    FeeAccount,
+    Governor,
+}
+
+impl WalletRole {
+    pub const ALL: [Self; 5] = [
+        Self::Admin,
+        Self::Operator,
+        Self::BlobOperator,
+        Self::FeeAccount,
+        Self::Governor,
+    ];
+
+    fn file_name(self) -> &'static str {
+        match self {
+            Self::Admin => "admin.json",
+            Self::Operator => "operator.json",
+            Self::BlobOperator => "blob_operator.json",
+            Self::FeeAccount => "fee_account.json",
+            Self::Governor => "governor.json",
+        }
+    }
+
+    /// Fixed BIP-44 path of the role key within the hyperchain mnemonic. Indices must never
+    /// change, otherwise the keys of the existing hyperchains can't be derived anymore.
+    fn derivation_path(self) -> String {
+        let index = match self {
+            Self::Admin => 0,
+            Self::Operator => 1,
+            Self::BlobOperator => 2,
+            Self::FeeAccount => 3,
+            Self::Governor => 4,
+        };
+        format!("m/44'/60'/0'/0/{index}")
+    }
+}
+
+/// Path to the keystore file of the wallet with the provided role.
+pub fn keystore_path(hyperchain_dir: &Path, role: WalletRole) -> PathBuf {
+    hyperchain_dir
+        .join(KEYSTORE_DIR_NAME)
+        .join(role.file_name())
+}
+
+/// Encrypts the private key and stores it as a Web3 Secret Storage (keystore v3) file.
+pub fn save_key(
+    shell: &Shell,
+    hyperchain_dir: &Path,
+    role: WalletRole,
+    pk: H256,
+) -> anyhow::Result<()> {
+    // The existing key may be the only access to the funds, so it's never overwritten.
+    let path = keystore_path(hyperchain_dir, role);
+    if shell.path_exists(&path) {
+        anyhow::bail!(
+            "Keystore {path:?} already exists. If it's left by an interrupted `init`, move it away to generate a new key"
+        );
+    }
+    let keystore_dir = hyperchain_dir.join(KEYSTORE_DIR_NAME);
+    shell.create_dir(&keystore_dir)?;
+    eth_keystore::encrypt_key(
+        &keystore_dir,
+        &mut rand::thread_rng(),
+        pk.as_bytes(),
+        passphrase(true)?,
+        Some(role.file_name()),
+    )
+    .with_context(|| format!("Unable to encrypt {role:?} key"))?;
+    Ok(())
+}
+
+/// Decrypts the private key of the wallet with the provided role.
+pub fn load_key(hyperchain_dir: &Path, role: WalletRole) -> anyhow::Result<H256> {
+    let path = keystore_path(hyperchain_dir, role);
+    decrypt_keystore(&path).with_context(|| format!("Unable to decrypt {role:?} key"))
+}
+
+/// Decrypts a keystore file protected by the hyperchain keystore passphrase.
+pub fn decrypt_keystore(path: &Path) -> anyhow::Result<H256> {
+    let pk = eth_keystore::decrypt_key(path, passphrase(false)?)
+        .with_context(|| format!("Unable to decrypt keystore {path:?}"))?;
+    if pk.len() != 32 {
+        anyhow::bail!("Keystore {path:?} contains a key of unexpected length");
+    }
+    Ok(H256::from_slice(&pk))
+}
+
+/// Passphrase for the keystores, either taken from the environment or prompted once.
+/// When the keystores are created, the prompted passphrase has to be repeated, since a typo
+/// would lock the keys forever.
+fn passphrase(confirm: bool) -> anyhow::Result<&'static str> {
+    if let Some(passphrase) = PASSPHRASE.get() {
+        return Ok(passphrase);
+    }
+    let passphrase = match std::env::var(PASSPHRASE_ENV) {
+        Ok(passphrase) => passphrase,
+        Err(_) => prompt_passphrase(confirm, |prompt| rpassword::prompt_password(prompt))?,
+    };
+    Ok(PASSPHRASE.get_or_init(|| passphrase))
+}
+
+/// Prompts the passphrase until it's entered the same way twice, if `confirm` is set.
+fn prompt_passphrase(
+    confirm: bool,
+    mut prompt: impl FnMut(&str) -> std::io::Result<String>,
+) -> std::io::Result<String> {
+    loop {
+        let passphrase = prompt("Keystore passphrase: ")?;
+        if !confirm || passphrase == prompt("Repeat keystore passphrase: ")? {
+            return Ok(passphrase);
+        }
+        println!("Passphrases don't match, try again");
+    }
+}
+
+/// Source of an existing key to be used instead of a generated one.
+#[derive(Debug, Clone)]
+pub enum KeySource {
+    /// Web3 Secret Storage (keystore v3) file.
+    Keystore(PathBuf),
+    /// File with a hex-encoded private key.
+    HexFile(PathBuf),
+    /// File with a BIP-39 mnemonic, and the derivation path for the key.
+    Mnemonic {
+        file: PathBuf,
+        derivation_path: String,
+    },
+}
+
+impl FromStr for KeySource {
+    type Err = anyhow::Error;
+
+    /// Parses `keystore:<path>`, `hex:<path>` or `mnemonic:<path>[:<derivation path>]`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, value) = s
+            .split_once(':')
+            .context("Expected `keystore:<path>`, `hex:<path>` or `mnemonic:<path>`")?;
+        if value.is_empty() {
+            anyhow::bail!("Key source {s} doesn't have a path");
+        }
+        match kind {
+            "keystore" => Ok(Self::Keystore(value.into())),
+            "hex" => Ok(Self::HexFile(value.into())),
+            "mnemonic" => {
+                let (file, derivation_path) = match value.rfind(":m/") {
+                    Some(idx) => (&value[..idx], &value[idx + 1..]),
+                    None => (value, DEFAULT_DERIVATION_PATH),
+                };
+                derivation_path.parse::<DerivationPath>().map_err(|err| {
+                    anyhow::anyhow!("Invalid derivation path {derivation_path}: {err}")
+                })?;
+                Ok(Self::Mnemonic {
+                    file: file.into(),
+                    derivation_path: derivation_path.to_string(),
+                })
+            }
+            _ => anyhow::bail!("Unknown key source: {kind}"),
+        }
+    }
+}
+
+impl KeySource {
+    pub fn load(&self) -> anyhow::Result<H256> {
+        let pk = match self {
+            Self::Keystore(path) => {
+                let passphrase =
+                    rpassword::prompt_password(format!("Passphrase for keystore {path:?}: "))?;
+                let pk = eth_keystore::decrypt_key(path, passphrase)
+                    .with_context(|| format!("Unable to decrypt keystore {path:?}"))?;
+                if pk.len() != 32 {
+                    anyhow::bail!("Keystore {path:?} contains a key of unexpected length");
+                }
+                H256::from_slice(&pk)
+            }
+            Self::HexFile(path) => {
+                let contents = std::fs::read_to_string(path)?;
+                let contents = contents.trim();
+                contents
+                    .strip_prefix("0x")
+                    .unwrap_or(contents)
+                    .parse()
+                    .with_context(|| format!("File {path:?} doesn't contain a hex private key"))?
+            }
+            Self::Mnemonic {
+                file,
+                derivation_path,
+            } => derive_key(&read_mnemonic(file)?, derivation_path)
+                .with_context(|| format!("Unable to derive key from mnemonic in {file:?}"))?,
+        };
+        if SecretKey::from_slice(pk.as_bytes()).is_err() {
+            anyhow::bail!("{self:?} doesn't contain a valid private key");
+        }
+        Ok(pk)
+    }
+}
+
+/// Existing key to be used for a wallet role.
+#[derive(Debug, Clone)]
+pub struct KeyImport {
+    pub source: KeySource,
+    /// Address the key is expected to have. If not provided, the user is asked to confirm it.
+    pub expected_address: Option<Address>,
+}
+
+impl KeyImport {
+    /// Loads the key and makes sure that it corresponds to the address the user expects.
+    pub fn load(&self, role: WalletRole) -> anyhow::Result<H256> {
+        let pk = self.source.load()?;
+        let address = crate::web3_client::address(pk);
+        match self.expected_address {
+            Some(expected) if expected != address => {
+                anyhow::bail!(
+                    "{role:?} key has address {address:?}, while {expected:?} was expected"
+                );
+            }
+            Some(_) => {}
+            None => {
+                println!("{role:?} wallet address is {address:?}. Is this correct? [y/N]");
+                let mut output = String::new();
+                std::io::stdin()
+                    .read_line(&mut output)
+                    .expect("We don't expect read from stdin to fail...");
+                if !matches!(output.trim(), "y" | "Y" | "yes") {
+                    anyhow::bail!("{role:?} key was rejected");
+                }
+            }
+        }
+        Ok(pk)
+    }
+}
+
+/// Generates a new 24-word mnemonic for the hyperchain wallets.
+pub fn generate_mnemonic() -> Mnemonic {
+    Mnemonic::generate(24).expect("24 is a valid number of words")
+}
+
+pub fn read_mnemonic(file: &Path) -> anyhow::Result<Mnemonic> {
+    let phrase = std::fs::read_to_string(file)?;
+    Mnemonic::parse(phrase.trim()).with_context(|| format!("Invalid mnemonic in {file:?}"))
+}
+
+/// Derives the key of the role from the hyperchain mnemonic.
+pub fn derive_role_key(mnemonic: &Mnemonic, role: WalletRole) -> anyhow::Result<H256> {
+    derive_key(mnemonic, &role.derivation_path())
+}
+
+fn derive_key(mnemonic: &Mnemonic, derivation_path: &str) -> anyhow::Result<H256> {
+    let path: DerivationPath = derivation_path
+        .parse()
+        .map_err(|err| anyhow::anyhow!("Invalid derivation path {derivation_path}: {err}"))?;
+    let key = XPrv::derive_from_path(mnemonic.to_seed(""), &path)
+        .map_err(|err| anyhow::anyhow!("Unable to derive key: {err}"))?;
+    Ok(H256::from(key.to_bytes()))
+}
+
+/// Signer for the wallet of the hyperchain, backed either by its keystore or the remote signer.
+pub fn signer(
+    hyperchain_dir: &Path,
+    state: &InitState,
+    role: WalletRole,
+) -> anyhow::Result<Box<dyn Signer>> {
+    if let (WalletRole::Admin, Some(url)) = (role, &state.remote_signer) {
+        let address = state.wallets[&WalletRole::Admin];
+        return Ok(Box::new(RemoteSigner::new(url, address)?));
+    }
+    let path = keystore_path(hyperchain_dir, role);
+    Ok(Box::new(KeystoreSigner::new(&path)?))
+}
+
+/// Loads the state of the hyperchain and connects to its L1.
+async fn load_hyperchain(
+    shell: &Shell,
+    name: &str,
+) -> anyhow::Result<(PathBuf, InitState, Web3Client)> {
+    let hyperchain_dir = utils::hyperchain_dir(name)?;
+    let state = InitState::load(shell, &hyperchain_dir)?;
+    let (Some(web3_rpc), Some(l1_network)) = (&state.web3_rpc, state.l1_network) else {
+        anyhow::bail!("Hyperchain {name} is not initialized");
+    };
+    if state.wallets.is_empty() {
+        anyhow::bail!("Hyperchain {name} doesn't have wallets yet");
+    }
+    let client = Web3Client::new(web3_rpc, l1_network)
+        .await?
+        .for_hyperchain(&hyperchain_dir)?;
+    Ok((hyperchain_dir, state, client))
+}
+
+/// Prints the address and the balance of every hyperchain wallet.
+pub async fn show(name: &str) -> anyhow::Result<()> {
+    let shell = Shell::new()?;
+    let (_, state, client) = load_hyperchain(&shell, name).await?;
+    let l1_network = state.l1_network.expect("Checked by `load_hyperchain`");
+    let multicall3 = PrerequisiteContracts::load(&shell, l1_network)?.multicall3;
+    let addresses: Vec<_> = state.wallets.values().copied().collect();
+    let balances = client.balances(multicall3, &addresses).await?;
+    for ((role, address), balance) in state.wallets.iter().zip(balances) {
+        let balance = utils::format_ether(balance);
+        println!("{role:?}: {address:?} ({balance} ETH)");
+    }
+    Ok(())
+}
+
+/// Sends ETH to the hyperchain wallets, either from the provided key or
+/// from the rich wallet on localhost.
+pub async fn fund(
+    name: &str,
+    role: Option<WalletRole>,
+    amount: &str,
+    from: Option<KeySource>,
+) -> anyhow::Result<()> {
+    let shell = Shell::new()?;
+    let (_, state, client) = load_hyperchain(&shell, name).await?;
+    let amount = utils::parse_ether(amount)?;
+    let pk = match (from, state.l1_network) {
+        (Some(source), _) => source.load()?,
+        (None, Some(L1Network::Localhost)) => localhost_rich_wallet(),
+        (None, _) => anyhow::bail!("Provide the key to fund the wallets from with `--from`"),
+    };
+    let funder = LocalSigner::new(pk)?;
+
+    let roles = role.map_or(WalletRole::ALL.to_vec(), |role| vec![role]);
+    for role in roles {
+        let Some(address) = state.wallets.get(&role) else {
+            continue;
+        };
+        client.transfer(&funder, *address, amount).await?;
+        println!(
+            "Sent {} ETH to {role:?} wallet {address:?}",
+            utils::format_ether(amount)
+        );
+    }
+    Ok(())
+}
+
+/// Transfers all the funds from the hyperchain wallets to the address.
+pub async fn sweep(name: &str, role: Option<WalletRole>, to: Address) -> anyhow::Result<()> {
+    let shell = Shell::new()?;
+    let (hyperchain_dir, state, client) = load_hyperchain(&shell, name).await?;
+
+    let roles = role.map_or(WalletRole::ALL.to_vec(), |role| vec![role]);
+    for role in roles {
+        if !state.wallets.contains_key(&role) {
+            continue;
+        }
+        let signer = signer(&hyperchain_dir, &state, role)?;
+        match client.sweep(signer.as_ref(), to).await? {
+            Some(receipt) => println!(
+                "Swept {role:?} wallet {:?} ({:?})",
+                signer.address(),
+                receipt.transaction_hash
+            ),
+            None => println!("{role:?} wallet doesn't have enough funds to cover the fee"),
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the key of the wallet with a newly generated one, moving the funds to it.
+pub async fn rotate(name: &str, role: WalletRole) -> anyhow::Result<()> {
+    if matches!(role, WalletRole::Admin | WalletRole::Governor) {
+        // These wallets own the L1 contracts, so the ownership must be transferred on-chain.
+        anyhow::bail!("{role:?} wallet cannot be rotated");
+    }
+    let shell = Shell::new()?;
+    let (hyperchain_dir, mut state, client) = load_hyperchain(&shell, name).await?;
+    if !state.wallets.contains_key(&role) {
+        anyhow::bail!("Hyperchain {name} doesn't have {role:?} wallet");
+    }
+
+    let (address, backup) = replace_key(&shell, &hyperchain_dir, role, state.wallets[&role])?;
+    let old_signer = KeystoreSigner::new(&backup)?;
+    let swept = client
+        .sweep(&old_signer, address)
+        .await
+        .with_context(|| {
+            format!("Unable to move the funds of {role:?} wallet to {address:?}, run `rotate` again to retry")
+        })?;
+    if swept.is_some() {
+        println!("Moved the funds of {role:?} wallet to {address:?}");
+    }
+    state.wallets.insert(role, address);
+    state.save(&shell, &hyperchain_dir)?;
+
+    println!("{role:?} wallet rotated, the new address is {address:?}");
+    println!("Note that the new key is not derived from the hyperchain mnemonic.");
+    Ok(())
+}
+
+/// Generates the new key of the wallet being rotated and returns its address along with the
+/// path the old keystore is moved to.
+fn replace_key(
+    shell: &Shell,
+    hyperchain_dir: &Path,
+    role: WalletRole,
+    old_address: Address,
+) -> anyhow::Result<(Address, PathBuf)> {
+    // Both keys are on disk before any funds are moved. The old keystore is kept next to
+    // the new one, named after its address, in case it's needed later.
+    let keystore = keystore_path(hyperchain_dir, role);
+    let backup = keystore.with_extension(format!("{old_address:?}.json"));
+    if shell.path_exists(&backup) {
+        // The previous rotation was interrupted after saving the new key, so it's reused.
+        println!("Resuming the rotation of {role:?} wallet, the old key is in {backup:?}");
+        return Ok((KeystoreSigner::new(&keystore)?.address(), backup));
+    }
+    let pk = crate::web3_client::gen_pk();
+    std::fs::rename(&keystore, &backup)?;
+    if let Err(err) = save_key(shell, hyperchain_dir, role, pk) {
+        std::fs::rename(&backup, &keystore)?;
+        return Err(err);
+    }
+    Ok((crate::web3_client::address(pk), backup))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keystores are encrypted with the same passphrase in all the tests.
+    fn use_test_passphrase() {
+        PASSPHRASE.get_or_init(|| "test passphrase".to_string());
+    }
+
+    #[test]
+    fn passphrase_is_prompted_until_confirmed() {
+        let mut answers = ["first", "typo", "second", "second"].into_iter();
+        let mut prompts = Vec::new();
+        let passphrase = prompt_passphrase(true, |prompt| {
+            prompts.push(prompt.to_string());
+            Ok(answers.next().unwrap().to_string())
+        })
+        .unwrap();
+        assert_eq!(passphrase, "second");
+        assert_eq!(prompts.len(), 4);
+        assert_eq!(prompts[1], "Repeat keystore passphrase: ");
+
+        // Existing keystores are only decrypted, so a typo is not fatal.
+        let mut prompts = 0;
+        let passphrase = prompt_passphrase(false, |_| {
+            prompts += 1;
+            Ok("first".to_string())
+        })
+        .unwrap();
+        assert_eq!((passphrase.as_str(), prompts), ("first", 1));
+    }
+
+    /// Mnemonic of the Hardhat and Anvil test accounts.
+    const TEST_MNEMONIC: &str = "test test test test test test test test test test test junk";
+
+    #[test]
+    fn key_sources_are_parsed() {
+        let KeySource::Keystore(path) = "keystore:/keys/admin.json".parse().unwrap() else {
+            panic!("Expected a keystore");
+        };
+        assert_eq!(path, Path::new("/keys/admin.json"));
+        let KeySource::HexFile(path) = "hex:key.txt".parse().unwrap() else {
+            panic!("Expected a hex file");
+        };
+        assert_eq!(path, Path::new("key.txt"));
+
+        let KeySource::Mnemonic {
+            file,
+            derivation_path,
+        } = "mnemonic:/keys/words.txt".parse().unwrap()
+        else {
+            panic!("Expected a mnemonic");
+        };
+        assert_eq!(file, Path::new("/keys/words.txt"));
+        assert_eq!(derivation_path, DEFAULT_DERIVATION_PATH);
+        // Paths may contain colons themselves.
+        let KeySource::Mnemonic {
+            file,
+            derivation_path,
+        } = "mnemonic:C:/words.txt:m/44'/60'/0'/0/7".parse().unwrap()
+        else {
+            panic!("Expected a mnemonic");
+        };
+        assert_eq!(file, Path::new("C:/words.txt"));
+        assert_eq!(derivation_path, "m/44'/60'/0'/0/7");
+    }
+
+    #[test]
+    fn invalid_key_sources_are_rejected() {
+        for (source, error) in [
+            ("/keys/admin.json", "Expected `keystore:<path>`"),
+            ("ledger:0", "Unknown key source: ledger"),
+            ("hex:", "Key source hex: doesn't have a path"),
+            (
+                "mnemonic:words.txt:m/44'/x",
+                "Invalid derivation path m/44'/x",
+            ),
+        ] {
+            let err = source.parse::<KeySource>().unwrap_err();
+            assert!(err.to_string().starts_with(error), "{source}: {err}");
+        }
+    }
+
+    #[test]
+    fn keys_are_loaded_from_files() {
+        let shell = Shell::new().unwrap();
+        let dir = shell.create_temp_dir().unwrap();
+        let pk = H256::repeat_byte(0x22);
+
+        let path = dir.path().join("key.txt");
+        shell.write_file(&path, format!("  {pk:?}\n")).unwrap();
+        assert_eq!(KeySource::HexFile(path.clone()).load().unwrap(), pk);
+        shell.write_file(&path, hex::encode(pk)).unwrap();
+        assert_eq!(KeySource::HexFile(path.clone()).load().unwrap(), pk);
+        shell.write_file(&path, "not a key").unwrap();
+        KeySource::HexFile(path.clone()).load().unwrap_err();
+        shell
+            .write_file(&path, format!("{:?}", H256::zero()))
+            .unwrap();
+        let err = KeySource::HexFile(path).load().unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("doesn't contain a valid private key"));
+
+        let path = dir.path().join("words.txt");
+        shell
+            .write_file(&path, format!("{TEST_MNEMONIC}\n"))
+            .unwrap();
+        let source: KeySource = format!("mnemonic:{}:m/44'/60'/0'/0/1", path.display())
+            .parse()
+            .unwrap();
+        let address = crate::web3_client::address(source.load().unwrap());
+        assert_eq!(
+            utils::to_checksum(address),
+            "0x70997970C51812dc3A010C7d01b50e0d17dc79C8"
+        );
+        shell.write_file(&path, "not a mnemonic").unwrap();
+        KeySource::Mnemonic {
+            file: path,
+            derivation_path: DEFAULT_DERIVATION_PATH.to_string(),
+        }
+        .load()
+        .unwrap_err();
+    }
+
+    #[test]
+    fn role_keys_are_pinned() {
+        let mnemonic = Mnemonic::parse(TEST_MNEMONIC).unwrap();
+        let expected = [
+            (
+                WalletRole::Admin,
+                "m/44'/60'/0'/0/0",
+                "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266",
+            ),
+            (
+                WalletRole::Operator,
+                "m/44'/60'/0'/0/1",
+                "0x70997970C51812dc3A010C7d01b50e0d17dc79C8",
+            ),
+            (
+                WalletRole::BlobOperator,
+                "m/44'/60'/0'/0/2",
+                "0x3C44CdDdB6a900fa2b585dd299e03d12FA4293BC",
+            ),
+            (
+                WalletRole::FeeAccount,
+                "m/44'/60'/0'/0/3",
+                "0x90F79bf6EB2c4f870365E785982E1f101E93b906",
+            ),
+            (
+                WalletRole::Governor,
+                "m/44'/60'/0'/0/4",
+                "0x15d34AAf54267DB7D7c367839AAf71A00a2C6A65",
+            ),
+        ];
+        assert_eq!(expected.len(), WalletRole::ALL.len());
+        for (role, path, address) in expected {
+            assert_eq!(role.derivation_path(), path, "{role:?}");
+            let pk = derive_role_key(&mnemonic, role).unwrap();
+            assert_eq!(
+                utils::to_checksum(crate::web3_client::address(pk)),
+                address,
+                "{role:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn keystore_is_never_overwritten() {
+        use_test_passphrase();
+        let shell = Shell::new().unwrap();
+        let dir = shell.create_temp_dir().unwrap();
+        let pk = H256::repeat_byte(0x33);
+        save_key(&shell, dir.path(), WalletRole::FeeAccount, pk).unwrap();
+        let err = save_key(
+            &shell,
+            dir.path(),
+            WalletRole::FeeAccount,
+            H256::repeat_byte(0x44),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("already exists"), "{err}");
+        assert_eq!(load_key(dir.path(), WalletRole::FeeAccount).unwrap(), pk);
+    }
+
+    #[test]
+    fn rotated_key_is_replaced_once() {
+        use_test_passphrase();
+        let shell = Shell::new().unwrap();
+        let dir = shell.create_temp_dir().unwrap();
+        let old_pk = H256::repeat_byte(0x55);
+        let old_address = crate::web3_client::address(old_pk);
+        save_key(&shell, dir.path(), WalletRole::Operator, old_pk).unwrap();
+
+        let (address, backup) =
+            replace_key(&shell, dir.path(), WalletRole::Operator, old_address).unwrap();
+        assert_ne!(address, old_address);
+        assert_eq!(KeystoreSigner::new(&backup).unwrap().address(), old_address);
+        let keystore = keystore_path(dir.path(), WalletRole::Operator);
+        assert_eq!(KeystoreSigner::new(&keystore).unwrap().address(), address);
+
+        // Interrupted rotation is resumed with the key generated the first time.
+        let resumed = replace_key(&shell, dir.path(), WalletRole::Operator, old_address).unwrap();
+        assert_eq!(resumed, (address, backup));
+    }
+
+    #[tokio::test]
+    async fn owner_wallets_are_not_rotated() {
+        for role in [WalletRole::Admin, WalletRole::Governor] {
+            let err = rotate("hyperchain", role).await.unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!("{role:?} wallet cannot be rotated")
+            );
+        }
+    }
+
+    #[test]
+    fn keystore_round_trip() {
+        use_test_passphrase();
+        let shell = Shell::new().unwrap();
+        let dir = shell.create_temp_dir().unwrap();
+        let pk = H256::repeat_byte(0x11);
+        save_key(&shell, dir.path(), WalletRole::Operator, pk).unwrap();
+        assert!(shell.path_exists(dir.path().join("keystore/operator.json")));
+        assert_eq!(load_key(dir.path(), WalletRole::Operator).unwrap(), pk);
+        load_key(dir.path(), WalletRole::Admin).unwrap_err();
+    }
+}
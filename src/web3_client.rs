@@ -1,14 +1,41 @@
-use std::time::Duration;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
 
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
 use web3::{
     contract::{tokens::Tokenize, Contract},
     ethabi::{self, Address},
-    signing::{Key, SecretKey, SecretKeyRef},
+    signing::{keccak256, Key, SecretKey, SecretKeyRef},
     transports::Http,
-    types::{H256, U256},
+    types::{
+        BlockNumber, Bytes, CallRequest, TransactionParameters, TransactionReceipt, H256, U256,
+    },
     Web3,
 };
 
+use crate::{
+    confirmations::ConfirmationConfig,
+    contracts::ContractRepr,
+    fees::{FeeConfig, Fees, FEE_HISTORY_BLOCKS},
+    init::L1Stage,
+    journal::{Journal, JournalEntry, TransactionStatus},
+    keyless::KeylessDeployment,
+    manifest::{DeployedContract, DeploymentManifest, OnChainContract},
+    revert,
+    signer::Signer,
+    L1Network,
+};
+
+const RECEIPT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Transactions not mined within this time are re-submitted with bumped fees.
+const STUCK_TRANSACTION_TIMEOUT: Duration = Duration::from_secs(180);
+const PENDING_TRANSACTIONS_FILE_NAME: &str = "pending_transactions.json";
+
 pub fn gen_pk() -> H256 {
     loop {
         let pk = H256::random();
@@ -27,32 +54,92 @@ pub fn address(pk: H256) -> Address {
 pub struct Web3Client {
     url: String,
     web3_client: Web3<Http>,
+    /// Chain ID of L1, used to sign all the transactions (EIP-155).
+    chain_id: u64,
+    l1_network: L1Network,
+    fee_config: FeeConfig,
+    confirmation_config: ConfirmationConfig,
+    nonce_manager: NonceManager,
+    journal: Option<Journal>,
+    /// Directory of the hyperchain whose deployment manifest records the deployed contracts.
+    hyperchain_dir: Option<PathBuf>,
+    /// Stage the transactions are sent for, recorded in the journal.
+    stage: Option<L1Stage>,
 }
 
 impl Web3Client {
-    pub fn new(web3_url: &str) -> anyhow::Result<Self> {
+    /// Connects to Web3 RPC and makes sure that it serves the expected L1 network,
+    /// so that no transaction gets signed for a wrong chain.
+    pub async fn new(web3_url: &str, l1_network: L1Network) -> anyhow::Result<Self> {
         let transport = Http::new(web3_url)?;
+        let web3_client = Web3::new(transport);
+        let chain_id = Self::wait_for_chain_id(&web3_client, web3_url).await?;
+        check_chain_id(web3_url, chain_id, l1_network)?;
         Ok(Self {
             url: web3_url.to_string(),
-            web3_client: Web3::new(transport),
+            web3_client,
+            chain_id: chain_id.as_u64(),
+            l1_network,
+            fee_config: FeeConfig::load(l1_network)?,
+            confirmation_config: ConfirmationConfig::load(l1_network)?,
+            nonce_manager: NonceManager::default(),
+            journal: None,
+            hyperchain_dir: None,
+            stage: None,
         })
     }
 
-    /// Tries to wait until corresponding Web3 is up and running.
-    pub async fn wait_until_up(&self) -> anyhow::Result<()> {
+    /// Persists the transactions sent on behalf of the hyperchain in its directory,
+    /// so that they are not sent twice if the wizard is restarted, and records them
+    /// in the hyperchain journal. Deployed contracts are recorded in the deployment manifest.
+    pub fn for_hyperchain(mut self, hyperchain_dir: &Path) -> anyhow::Result<Self> {
+        self.nonce_manager =
+            NonceManager::load(hyperchain_dir.join(PENDING_TRANSACTIONS_FILE_NAME))?;
+        self.journal = Some(Journal::new(hyperchain_dir));
+        self.hyperchain_dir = Some(hyperchain_dir.to_path_buf());
+        Ok(self)
+    }
+
+    /// Records the stage the transactions are sent for in the journal.
+    pub fn in_stage(mut self, stage: L1Stage) -> Self {
+        self.stage = Some(stage);
+        self
+    }
+
+    /// Tries to wait until corresponding Web3 is up and running, and returns its chain ID.
+    async fn wait_for_chain_id(web3_client: &Web3<Http>, web3_url: &str) -> anyhow::Result<U256> {
         // 100 retries with 200ms frequency give us 20 seconds to wait.
         for _ in 0..100 {
-            if self.chain_id().await.is_ok() {
-                return Ok(());
+            if let Ok(chain_id) = web3_client.eth().chain_id().await {
+                return Ok(chain_id);
             }
             tokio::time::sleep(Duration::from_millis(200)).await;
         }
-        anyhow::bail!("Web3 RPC with URL {} does not respond", self.url);
+        anyhow::bail!("Web3 RPC with URL {web3_url} does not respond");
+    }
+
+    pub fn chain_id(&self) -> u64 {
+        self.chain_id
     }
 
-    pub async fn chain_id(&self) -> anyhow::Result<U256> {
-        let id = self.web3_client.eth().chain_id().await?;
-        Ok(id)
+    /// Number of the account transactions included in the latest block.
+    async fn mined_nonce(&self, address: Address) -> anyhow::Result<U256> {
+        let nonce = self
+            .web3_client
+            .eth()
+            .transaction_count(address, Some(BlockNumber::Latest))
+            .await?;
+        Ok(nonce)
+    }
+
+    /// Next nonce of the account, including pending transactions.
+    pub async fn nonce(&self, address: Address) -> anyhow::Result<U256> {
+        let nonce = self
+            .web3_client
+            .eth()
+            .transaction_count(address, Some(BlockNumber::Pending))
+            .await?;
+        Ok(nonce)
     }
 
     pub async fn balance_of(&self, address: Address) -> anyhow::Result<U256> {
@@ -60,18 +147,1313 @@ impl Web3Client {
         Ok(balance)
     }
 
+    /// Deploys the contract and makes sure that the deployed code matches the artifact.
     pub async fn deploy<P: Tokenize>(
         &self,
-        pk: H256,
-        json: &[u8],
-        bytecode: String,
+        signer: &dyn Signer,
+        name: &str,
+        contract: &ContractRepr,
         constructor_args: P,
     ) -> anyhow::Result<Contract<Http>> {
-        let chain_id = None; // TODO should not be none.
-        let pk = SecretKey::from_slice(pk.as_bytes()).unwrap();
-        let contract = Contract::deploy(self.web3_client.eth(), json)?
-            .sign_with_key_and_execute(bytecode, constructor_args, &pk, chain_id)
+        if !contract.link_references.is_empty() {
+            anyhow::bail!("{name} references libraries, link it with `Contracts::link` first");
+        }
+        let abi = contract.abi.clone();
+        let code = contract.bytecode.clone();
+        let args = constructor_args.into_tokens();
+        let data = match abi.constructor() {
+            Some(constructor) => constructor.encode_input(code, &args)?,
+            None => code,
+        };
+
+        let receipt = self
+            .send_transaction(
+                signer,
+                &format!("Deploy {name}"),
+                None,
+                data.into(),
+                U256::zero(),
+                Some(&abi),
+            )
+            .await?;
+        let address = receipt
+            .contract_address
+            .context("Deployment receipt doesn't contain contract address")?;
+        self.verify_code(name, contract, address).await?;
+        self.record_deployment(name, address, Some(&receipt), ethabi::encode(&args), true)
+            .await?;
+        Ok(Contract::new(self.web3_client.eth(), address, abi))
+    }
+
+    /// Deploys the contract through the CREATE2 factory, so that its address only depends on
+    /// the factory, the salt and the init code. Deployment is skipped if the contract is
+    /// already at the predicted address.
+    pub async fn deploy_create2<P: Tokenize>(
+        &self,
+        signer: &dyn Signer,
+        factory: Address,
+        salt: H256,
+        name: &str,
+        contract: &ContractRepr,
+        constructor_args: P,
+    ) -> anyhow::Result<Contract<Http>> {
+        if !contract.link_references.is_empty() {
+            anyhow::bail!("{name} references libraries, link it with `Contracts::link` first");
+        }
+        let args = constructor_args.into_tokens();
+        let (address, data) = create2_deployment(factory, salt, contract, &args)?;
+
+        let receipt = if !self.code(address).await?.0.is_empty() {
+            println!("{name} is already deployed to {address:?}");
+            None
+        } else {
+            let receipt = self
+                .send_transaction(
+                    signer,
+                    &format!("Deploy {name} with CREATE2"),
+                    Some(factory),
+                    data,
+                    U256::zero(),
+                    // Reverts come from the factory, the contract constructor errors are swallowed.
+                    Some(&create2_factory_abi()),
+                )
+                .await?;
+            // The factory doesn't revert if the deployment fails, it just returns zero address.
+            if self.code(address).await?.0.is_empty() {
+                anyhow::bail!("{name} was not deployed to the predicted address {address:?}");
+            }
+            println!("Deployed {name} to {address:?}");
+            Some(receipt)
+        };
+        self.verify_code(name, contract, address).await?;
+        let deployed = receipt.is_some();
+        self.record_deployment(
+            name,
+            address,
+            receipt.as_ref(),
+            ethabi::encode(&args),
+            deployed,
+        )
+        .await?;
+        Ok(Contract::new(
+            self.web3_client.eth(),
+            address,
+            contract.abi.clone(),
+        ))
+    }
+
+    /// Deploys the contract with its pre-signed keyless transaction, funding the deployer
+    /// from `funder`. Skipped if the contract is already deployed.
+    pub async fn deploy_keyless(
+        &self,
+        funder: &dyn Signer,
+        deployment: &KeylessDeployment,
+    ) -> anyhow::Result<Address> {
+        let name = deployment.name;
+        let address = deployment.address;
+        if !self.code(address).await?.0.is_empty() {
+            println!("{name} is already deployed to {address:?}");
+            return Ok(address);
+        }
+        if !self.nonce(deployment.deployer).await?.is_zero() {
+            anyhow::bail!(
+                "{name} deployer {:?} has already sent transactions, so {name} can't be deployed to {address:?}",
+                deployment.deployer
+            );
+        }
+
+        let balance = self.balance_of(deployment.deployer).await?;
+        if balance < deployment.cost() {
+            self.transfer(funder, deployment.deployer, deployment.cost() - balance)
+                .await?;
+        }
+        let sent = self
+            .broadcast(&format!("Deploy {name} keyless"), deployment.raw_tx())
+            .await;
+        if let Err(err) = sent {
+            // The transaction is not protected against replays, and nodes refuse such
+            // over RPC by default.
+            let unprotected = err
+                .downcast_ref::<web3::Error>()
+                .is_some_and(is_unprotected_rejection);
+            if unprotected {
+                anyhow::bail!(
+                    "L1 node at {} doesn't accept transactions without replay protection, so {name} can't be deployed to its canonical address. Restart the node with `--rpc.allow-unprotected-txs` (geth), or deploy {name} to {address:?} with another node",
+                    self.url
+                );
+            }
+            return Err(err)
+                .with_context(|| format!("Unable to send {name} keyless deployment transaction"));
+        }
+        println!("Deployed {name} to {address:?}");
+        Ok(address)
+    }
+
+    /// Records the contract deployed to the address in the deployment manifest of the hyperchain,
+    /// if the client sends transactions on behalf of one. Contracts deployed elsewhere are
+    /// recorded without `receipt`.
+    pub async fn record_deployment(
+        &self,
+        name: &str,
+        address: Address,
+        receipt: Option<&TransactionReceipt>,
+        constructor_args: Vec<u8>,
+        replace: bool,
+    ) -> anyhow::Result<()> {
+        let Some(hyperchain_dir) = &self.hyperchain_dir else {
+            return Ok(());
+        };
+        let on_chain = OnChainContract::read(self, address).await?;
+        let contract = DeployedContract {
+            address,
+            deploy_tx: receipt.map(|receipt| receipt.transaction_hash),
+            block_number: receipt
+                .and_then(|receipt| receipt.block_number)
+                .map(|number| number.as_u64()),
+            constructor_args: constructor_args.into(),
+            bytecode_hash: on_chain.bytecode_hash,
+            implementation: on_chain.implementation,
+            proxy_admin: on_chain.proxy_admin,
+            owner: on_chain.owner,
+            governor: on_chain.governor,
+            admin: on_chain.admin,
+        };
+        DeploymentManifest::record(
+            &xshell::Shell::new()?,
+            hyperchain_dir,
+            self.l1_network,
+            name,
+            contract,
+            replace,
+        )
+    }
+
+    /// Makes sure that the code at the address matches the contract artifact.
+    pub async fn verify_code(
+        &self,
+        name: &str,
+        contract: &ContractRepr,
+        address: Address,
+    ) -> anyhow::Result<()> {
+        if let Some(expected) = contract.expected_code(address) {
+            let deployed = keccak256(&self.code(address).await?.0);
+            if deployed != keccak256(&expected) {
+                anyhow::bail!("Code deployed to {address:?} doesn't match {name} artifact");
+            }
+        }
+        Ok(())
+    }
+
+    /// Populates the transaction, signs it with the provided signer and sends it to L1.
+    /// Waits until the transaction is included in a block.
+    pub async fn send_transaction(
+        &self,
+        signer: &dyn Signer,
+        description: &str,
+        to: Option<Address>,
+        data: Bytes,
+        value: U256,
+        abi: Option<&ethabi::Contract>,
+    ) -> anyhow::Result<TransactionReceipt> {
+        let intent = intent_hash(signer.address(), to, &data, value);
+        if let Some(pending) = self.nonce_manager.pending(intent) {
+            // Sent by a previous run of the wizard, so it must not be sent again.
+            println!(
+                "Transaction with nonce {} was already sent, waiting for it",
+                pending.nonce
+            );
+            let mut tx = TransactionParameters {
+                nonce: Some(pending.nonce),
+                to,
+                gas: pending.gas,
+                value,
+                data,
+                chain_id: Some(self.chain_id),
+                ..Default::default()
+            };
+            pending.fees.apply(&mut tx);
+            return self
+                .wait_or_replace(signer, description, tx, pending.hashes)
+                .await;
+        }
+        self.simulate(description, signer.address(), to, &data, value, abi)
+            .await?;
+        let tx = self
+            .populate_transaction(signer.address(), None, to, data, value, abi)
+            .await?;
+        self.send_populated(signer, description, tx).await
+    }
+
+    /// Executes the transaction with `eth_call` to catch reverts before sending it.
+    /// The revert reason is decoded, using the contract ABI for custom errors.
+    pub async fn simulate(
+        &self,
+        description: &str,
+        from: Address,
+        to: Option<Address>,
+        data: &Bytes,
+        value: U256,
+        abi: Option<&ethabi::Contract>,
+    ) -> anyhow::Result<()> {
+        let request = CallRequest {
+            from: Some(from),
+            to,
+            value: Some(value),
+            data: Some(data.clone()),
+            ..Default::default()
+        };
+        let Err(err) = self.web3_client.eth().call(request, None).await else {
+            return Ok(());
+        };
+        let Some(data) = revert::revert_data(&err) else {
+            return Err(err).with_context(|| format!("Unable to simulate '{description}'"));
+        };
+        let stage = self
+            .stage
+            .map(|stage| format!(" in stage {stage}"))
+            .unwrap_or_default();
+        anyhow::bail!(
+            "'{description}'{stage} {}",
+            revert::decode_revert(&data, abi)
+        );
+    }
+
+    /// Transfers ETH to the address.
+    pub async fn transfer(
+        &self,
+        signer: &dyn Signer,
+        to: Address,
+        value: U256,
+    ) -> anyhow::Result<TransactionReceipt> {
+        // The recipient may be a contract, so the gas is estimated rather than fixed.
+        let tx = self
+            .populate_transaction(
+                signer.address(),
+                None,
+                Some(to),
+                Bytes::default(),
+                value,
+                None,
+            )
             .await?;
-        Ok(contract)
+        self.send_populated(signer, &format!("Transfer to {to:?}"), tx)
+            .await
+    }
+
+    /// Signs and sends the populated transaction, replacing it with bumped fees if it gets stuck.
+    async fn send_populated(
+        &self,
+        signer: &dyn Signer,
+        description: &str,
+        tx: TransactionParameters,
+    ) -> anyhow::Result<TransactionReceipt> {
+        self.wait_or_replace(signer, description, tx, Vec::new())
+            .await
+    }
+
+    /// Sends the transaction unless one of `sent` hashes is already mined, and waits for it.
+    /// Each time the transaction is not mined within the timeout, it's replaced by
+    /// the same transaction with bumped fees.
+    async fn wait_or_replace(
+        &self,
+        signer: &dyn Signer,
+        description: &str,
+        mut tx: TransactionParameters,
+        mut sent: Vec<H256>,
+    ) -> anyhow::Result<TransactionReceipt> {
+        let intent = intent_hash(signer.address(), tx.to, &tx.data, tx.value);
+        let nonce = tx.nonce.expect("Transaction must be populated");
+        let mut should_send = sent.is_empty();
+        loop {
+            if should_send {
+                let raw_tx = signer.sign_transaction(tx.clone()).await?;
+                let hash = H256(keccak256(&raw_tx.0));
+                if let Some(previous) = sent.last() {
+                    self.record_unmined(
+                        description,
+                        tx.to,
+                        *previous,
+                        nonce,
+                        TransactionStatus::Replaced,
+                    )?;
+                }
+                // Record the hash first, so that a crash right after sending doesn't lose it.
+                sent.push(hash);
+                self.nonce_manager.record(PendingTransaction {
+                    from: signer.address(),
+                    nonce,
+                    gas: tx.gas,
+                    fees: Fees::of(&tx),
+                    intent,
+                    hashes: sent.clone(),
+                })?;
+                self.record_unmined(description, tx.to, hash, nonce, TransactionStatus::Pending)?;
+                if let Err(err) = self.web3_client.eth().send_raw_transaction(raw_tx).await {
+                    // Another of the sent transactions may be mined or known to the node
+                    // already, so the receipts of all of them are still waited for.
+                    if !is_superseded(&err) {
+                        self.record_unmined(
+                            description,
+                            tx.to,
+                            hash,
+                            nonce,
+                            TransactionStatus::Dropped,
+                        )?;
+                        // Nothing with this nonce reached L1, so it's freed for the next
+                        // transaction. Otherwise, the earlier transactions can still be mined.
+                        if sent.len() == 1 {
+                            self.nonce_manager.release(intent)?;
+                        }
+                        return Err(err.into());
+                    }
+                    println!("Transaction {hash:?} is not accepted by L1: {err}");
+                }
+            }
+
+            let deadline = Instant::now() + STUCK_TRANSACTION_TIMEOUT;
+            while Instant::now() < deadline {
+                // Nonce is checked before the receipts, so if it's used by one of our
+                // transactions, its receipt is guaranteed to be found.
+                let mined_nonce = self.mined_nonce(signer.address()).await?;
+                for (i, hash) in sent.iter().enumerate() {
+                    let Some(receipt) = self.receipt(*hash).await? else {
+                        continue;
+                    };
+                    // If the block is reorged out, the transaction is waited for again.
+                    if let Some(receipt) = self.wait_for_confirmations(receipt).await? {
+                        self.nonce_manager.remove(intent)?;
+                        // Replacements sent after the mined transaction won't be mined.
+                        for dropped in &sent[i + 1..] {
+                            self.record_unmined(
+                                description,
+                                tx.to,
+                                *dropped,
+                                nonce,
+                                TransactionStatus::Dropped,
+                            )?;
+                        }
+                        return self.finalize(description, nonce, receipt).await;
+                    }
+                }
+                if mined_nonce > nonce {
+                    self.nonce_manager.remove(intent)?;
+                    if let Some(last) = sent.last() {
+                        self.record_unmined(
+                            description,
+                            tx.to,
+                            *last,
+                            nonce,
+                            TransactionStatus::Dropped,
+                        )?;
+                    }
+                    anyhow::bail!("Nonce {nonce} is already used by another transaction");
+                }
+                tokio::time::sleep(RECEIPT_POLL_INTERVAL).await;
+            }
+
+            let current = self.fees().await?;
+            match self.fee_config.bump(Fees::of(&tx), current) {
+                Some(fees) => {
+                    println!(
+                        "Transaction {:?} is not mined in {}s, replacing it with {fees}",
+                        sent.last().unwrap(),
+                        STUCK_TRANSACTION_TIMEOUT.as_secs()
+                    );
+                    fees.apply(&mut tx);
+                    should_send = true;
+                }
+                None => {
+                    println!(
+                        "Transaction {:?} is not mined yet, but its fees are already at the cap",
+                        sent.last().unwrap()
+                    );
+                    should_send = false;
+                }
+            }
+        }
+    }
+
+    /// Transfers the whole balance of the signer (minus the fee) to the address.
+    /// Returns `None` if the balance doesn't cover the fee.
+    pub async fn sweep(
+        &self,
+        signer: &dyn Signer,
+        to: Address,
+    ) -> anyhow::Result<Option<TransactionReceipt>> {
+        let balance = self.balance_of(signer.address()).await?;
+        let gas = self
+            .estimate_gas(signer.address(), Some(to), &Bytes::default(), balance, None)
+            .await?;
+        let mut tx = self
+            .populate_with_gas(
+                signer.address(),
+                None,
+                Some(to),
+                Bytes::default(),
+                balance,
+                gas,
+            )
+            .await?;
+        // With EIP-1559 fees the unused part of the max fee stays on the account.
+        let max_fee_per_gas = tx.max_fee_per_gas.or(tx.gas_price);
+        let fee = tx.gas * max_fee_per_gas.expect("Transaction must be populated");
+        if balance <= fee {
+            return Ok(None);
+        }
+        tx.value = balance - fee;
+        let raw_tx = signer.sign_transaction(tx).await?;
+        self.broadcast(&format!("Sweep to {to:?}"), raw_tx)
+            .await
+            .map(Some)
+    }
+
+    /// Fills in everything required to sign the transaction: nonce, gas, fees and chain ID.
+    /// If nonce is not provided, the next nonce of the sender is used. The contract ABI
+    /// is used to decode custom errors if gas estimation reverts.
+    pub async fn populate_transaction(
+        &self,
+        from: Address,
+        nonce: Option<U256>,
+        to: Option<Address>,
+        data: Bytes,
+        value: U256,
+        abi: Option<&ethabi::Contract>,
+    ) -> anyhow::Result<TransactionParameters> {
+        let gas = self.estimate_gas(from, to, &data, value, abi).await?;
+        self.populate_with_gas(from, nonce, to, data, value, gas)
+            .await
+    }
+
+    /// Gas limit for the transaction: the estimated gas with the margin from the fee config.
+    async fn estimate_gas(
+        &self,
+        from: Address,
+        to: Option<Address>,
+        data: &Bytes,
+        value: U256,
+        abi: Option<&ethabi::Contract>,
+    ) -> anyhow::Result<U256> {
+        let request = CallRequest {
+            from: Some(from),
+            to,
+            value: Some(value),
+            data: Some(data.clone()),
+            ..Default::default()
+        };
+        let estimated = match self.web3_client.eth().estimate_gas(request, None).await {
+            Ok(estimated) => estimated,
+            Err(err) => match revert::revert_data(&err) {
+                Some(data) => anyhow::bail!(
+                    "Unable to estimate gas: transaction {}",
+                    revert::decode_revert(&data, abi)
+                ),
+                None => return Err(err).context("Unable to estimate gas"),
+            },
+        };
+        Ok(self.fee_config.gas_limit(estimated))
+    }
+
+    async fn populate_with_gas(
+        &self,
+        from: Address,
+        nonce: Option<U256>,
+        to: Option<Address>,
+        data: Bytes,
+        value: U256,
+        gas: U256,
+    ) -> anyhow::Result<TransactionParameters> {
+        let nonce = match nonce {
+            Some(nonce) => nonce,
+            None => {
+                let nonce = self.nonce(from).await?;
+                self.nonce_manager.next_nonce(from, nonce)
+            }
+        };
+        let fees = self.fees().await?;
+        let mut tx = TransactionParameters {
+            nonce: Some(nonce),
+            to,
+            gas,
+            value,
+            data,
+            chain_id: Some(self.chain_id),
+            ..Default::default()
+        };
+        fees.apply(&mut tx);
+        Ok(tx)
+    }
+
+    /// Chooses the fees according to the fee config: EIP-1559 fees based on `eth_feeHistory`,
+    /// or the legacy gas price if the network doesn't support EIP-1559.
+    pub async fn fees(&self) -> anyhow::Result<Fees> {
+        let eth = self.web3_client.eth();
+        if !self.fee_config.legacy {
+            let percentile = self.fee_config.priority_fee_percentile;
+            let history = eth
+                .fee_history(
+                    FEE_HISTORY_BLOCKS.into(),
+                    BlockNumber::Latest,
+                    Some(vec![percentile]),
+                )
+                .await;
+            // The last base fee is the one of the next block. It's zero before London.
+            match history {
+                Ok(history) => {
+                    if let Some(base_fee) =
+                        history.base_fee_per_gas.last().filter(|fee| !fee.is_zero())
+                    {
+                        let rewards = history
+                            .reward
+                            .unwrap_or_default()
+                            .into_iter()
+                            .filter_map(|block| block.first().copied())
+                            .collect();
+                        return self.fee_config.eip1559_fees(*base_fee, rewards);
+                    }
+                }
+                Err(err) if is_unsupported_method(&err) => {}
+                Err(err) => return Err(err).context("Unable to get L1 fee history"),
+            }
+            println!("L1 doesn't support EIP-1559, falling back to legacy transactions");
+        }
+        self.fee_config.legacy_fees(eth.gas_price().await?)
+    }
+
+    /// Sends the signed transaction and waits until it is included in a block.
+    pub async fn broadcast(
+        &self,
+        description: &str,
+        raw_tx: Bytes,
+    ) -> anyhow::Result<TransactionReceipt> {
+        // Recorded before sending, so that the transaction shows up in the journal if it gets stuck.
+        let hash = H256(keccak256(&raw_tx.0));
+        let nonce = raw_tx_nonce(&raw_tx.0).context("Invalid signed transaction")?;
+        self.record_unmined(description, None, hash, nonce, TransactionStatus::Pending)?;
+        let receipt = self
+            .web3_client
+            .send_raw_transaction_with_confirmation(
+                raw_tx,
+                RECEIPT_POLL_INTERVAL,
+                self.confirmation_config.confirmations as usize,
+            )
+            .await?;
+        self.finalize(description, nonce, receipt).await
+    }
+
+    /// Records the status of the transaction that is not mined in the journal.
+    fn record_unmined(
+        &self,
+        description: &str,
+        to: Option<Address>,
+        hash: H256,
+        nonce: U256,
+        status: TransactionStatus,
+    ) -> anyhow::Result<()> {
+        if let Some(journal) = &self.journal {
+            journal.append(&JournalEntry::unmined(
+                self.stage,
+                description,
+                to,
+                hash,
+                nonce,
+                status,
+            )?)?;
+        }
+        Ok(())
+    }
+
+    /// Waits until the block with the transaction gets enough confirmations.
+    /// Returns `None` if the transaction is no longer in the chain by then.
+    async fn wait_for_confirmations(
+        &self,
+        receipt: TransactionReceipt,
+    ) -> anyhow::Result<Option<TransactionReceipt>> {
+        let confirmations = self.confirmation_config.confirmations;
+        let Some(block_number) = receipt.block_number else {
+            return Ok(None);
+        };
+        if confirmations == 0 {
+            return Ok(Some(receipt));
+        }
+        println!(
+            "Waiting for {confirmations} confirmations of {:?}",
+            receipt.transaction_hash
+        );
+        let target = block_number.as_u64() + confirmations;
+        while self.web3_client.eth().block_number().await?.as_u64() < target {
+            tokio::time::sleep(RECEIPT_POLL_INTERVAL).await;
+        }
+        let confirmed = self.receipt(receipt.transaction_hash).await?;
+        Ok(confirmed.filter(|confirmed| confirmed.block_hash == receipt.block_hash))
+    }
+
+    /// Records the mined transaction in the journal and checks that it has succeeded
+    /// and, for deployments, that the contract code is there.
+    async fn finalize(
+        &self,
+        description: &str,
+        nonce: U256,
+        receipt: TransactionReceipt,
+    ) -> anyhow::Result<TransactionReceipt> {
+        if let Some(journal) = &self.journal {
+            journal.append(&JournalEntry::new(
+                self.stage,
+                description,
+                nonce,
+                &receipt,
+            )?)?;
+        }
+        if receipt.status != Some(1.into()) {
+            anyhow::bail!("Transaction {:?} has failed", receipt.transaction_hash);
+        }
+        if let Some(address) = receipt.contract_address {
+            if self.code(address).await?.0.is_empty() {
+                anyhow::bail!(
+                    "Transaction {:?} didn't deploy any code to {address:?}",
+                    receipt.transaction_hash
+                );
+            }
+        }
+        Ok(receipt)
+    }
+
+    /// Calls the contract with `eth_call` at the latest block.
+    pub async fn call(&self, to: Address, data: Bytes) -> anyhow::Result<Bytes> {
+        let request = CallRequest {
+            to: Some(to),
+            data: Some(data),
+            ..Default::default()
+        };
+        let output = self.web3_client.eth().call(request, None).await?;
+        Ok(output)
+    }
+
+    /// Executes the view calls in a single `eth_call` through Multicall3 `aggregate3`.
+    /// Returns the output of each call, or `None` if it has failed.
+    pub async fn multicall(
+        &self,
+        multicall3: Address,
+        calls: Vec<(Address, Bytes)>,
+    ) -> anyhow::Result<Vec<Option<Bytes>>> {
+        let calls_count = calls.len();
+        let output = self.call(multicall3, encode_aggregate3(calls)).await?;
+        decode_aggregate3(&output.0, calls_count)
+    }
+
+    /// Balances of the addresses, fetched with a single call to Multicall3.
+    pub async fn balances(
+        &self,
+        multicall3: Address,
+        addresses: &[Address],
+    ) -> anyhow::Result<Vec<U256>> {
+        let get_eth_balance =
+            ethabi::short_signature("getEthBalance", &[ethabi::ParamType::Address]);
+        let calls = addresses
+            .iter()
+            .map(|address| {
+                let args = ethabi::encode(&[ethabi::Token::Address(*address)]);
+                (
+                    multicall3,
+                    [get_eth_balance.as_slice(), &args].concat().into(),
+                )
+            })
+            .collect();
+        self.multicall(multicall3, calls)
+            .await?
+            .into_iter()
+            .zip(addresses)
+            .map(|(output, address)| match output {
+                Some(output) if output.0.len() == 32 => Ok(U256::from_big_endian(&output.0)),
+                _ => anyhow::bail!("Unable to get balance of {address:?}"),
+            })
+            .collect()
+    }
+
+    pub async fn code(&self, address: Address) -> anyhow::Result<Bytes> {
+        let code = self.web3_client.eth().code(address, None).await?;
+        Ok(code)
+    }
+
+    /// Value of the storage slot of the contract at the latest block.
+    pub async fn storage(&self, address: Address, slot: U256) -> anyhow::Result<H256> {
+        let value = self.web3_client.eth().storage(address, slot, None).await?;
+        Ok(value)
+    }
+
+    pub async fn receipt(&self, tx_hash: H256) -> anyhow::Result<Option<TransactionReceipt>> {
+        let receipt = self.web3_client.eth().transaction_receipt(tx_hash).await?;
+        Ok(receipt)
+    }
+}
+
+/// Calldata of Multicall3 `aggregate3` executing the calls, each allowed to fail.
+fn encode_aggregate3(calls: Vec<(Address, Bytes)>) -> Bytes {
+    use ethabi::{ParamType, Token};
+
+    let call_type = ParamType::Tuple(vec![ParamType::Address, ParamType::Bool, ParamType::Bytes]);
+    let calls = calls
+        .into_iter()
+        .map(|(target, data)| {
+            Token::Tuple(vec![
+                Token::Address(target),
+                Token::Bool(true),
+                Token::Bytes(data.0),
+            ])
+        })
+        .collect();
+    let selector = ethabi::short_signature("aggregate3", &[ParamType::Array(Box::new(call_type))]);
+    [selector.as_slice(), &ethabi::encode(&[Token::Array(calls)])]
+        .concat()
+        .into()
+}
+
+/// Outputs of the calls from the Multicall3 `aggregate3` result, `None` for the failed ones.
+fn decode_aggregate3(output: &[u8], calls_count: usize) -> anyhow::Result<Vec<Option<Bytes>>> {
+    use ethabi::{ParamType, Token};
+
+    let result_type = ParamType::Tuple(vec![ParamType::Bool, ParamType::Bytes]);
+    let tokens = ethabi::decode(&[ParamType::Array(Box::new(result_type))], output)
+        .context("Unexpected Multicall3 output")?;
+    let Some(Token::Array(results)) = tokens.into_iter().next() else {
+        anyhow::bail!("Unexpected Multicall3 output");
+    };
+    if results.len() != calls_count {
+        anyhow::bail!(
+            "Multicall3 returned {} results for {calls_count} calls",
+            results.len()
+        );
+    }
+    let results = results
+        .into_iter()
+        .map(|result| match result {
+            Token::Tuple(result) => match result.as_slice() {
+                [Token::Bool(true), Token::Bytes(output)] => Some(output.clone().into()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    Ok(results)
+}
+
+/// Makes sure that Web3 RPC serves the expected L1 network.
+fn check_chain_id(web3_url: &str, chain_id: U256, l1_network: L1Network) -> anyhow::Result<()> {
+    if chain_id != U256::from(l1_network.chain_id()) {
+        anyhow::bail!(
+            "Web3 RPC with URL {web3_url} serves chain {chain_id}, while {l1_network} has chain ID {}",
+            l1_network.chain_id()
+        );
+    }
+    Ok(())
+}
+
+/// Address of the contract deployed with CREATE2 by `deployer`.
+pub fn create2_address(deployer: Address, salt: H256, init_code: &[u8]) -> Address {
+    let mut bytes = vec![0xff];
+    bytes.extend_from_slice(deployer.as_bytes());
+    bytes.extend_from_slice(salt.as_bytes());
+    bytes.extend_from_slice(&keccak256(init_code));
+    Address::from_slice(&keccak256(&bytes)[12..])
+}
+
+/// Address of the contract deployed through the EIP-2470 singleton factory, and the calldata
+/// of the factory call deploying it.
+pub fn create2_deployment(
+    factory: Address,
+    salt: H256,
+    contract: &ContractRepr,
+    constructor_args: &[ethabi::Token],
+) -> anyhow::Result<(Address, Bytes)> {
+    let code = contract.bytecode.clone();
+    let init_code = match contract.abi.constructor() {
+        Some(constructor) => constructor.encode_input(code, constructor_args)?,
+        None => code,
+    };
+    let address = create2_address(factory, salt, &init_code);
+    // `deploy(bytes _initCode, bytes32 _salt)` of the factory.
+    let deploy = ethabi::short_signature(
+        "deploy",
+        &[ethabi::ParamType::Bytes, ethabi::ParamType::FixedBytes(32)],
+    );
+    let args = ethabi::encode(&[
+        ethabi::Token::Bytes(init_code),
+        ethabi::Token::FixedBytes(salt.as_bytes().to_vec()),
+    ]);
+    Ok((address, [deploy.as_slice(), &args].concat().into()))
+}
+
+/// Nonce of the signed transaction, either legacy or typed (EIP-2718).
+fn raw_tx_nonce(raw_tx: &[u8]) -> anyhow::Result<U256> {
+    let (payload, nonce_index) = match raw_tx.first() {
+        // Typed transactions start with the type, followed by the RLP list starting with chain ID.
+        Some(tx_type) if *tx_type < 0xc0 => (&raw_tx[1..], 1),
+        _ => (raw_tx, 0),
+    };
+    let nonce: Vec<u8> = rlp::Rlp::new(payload).val_at(nonce_index)?;
+    if nonce.len() > 32 {
+        anyhow::bail!("Nonce is too long");
+    }
+    Ok(U256::from_big_endian(&nonce))
+}
+
+/// Whether the node refused the transaction because it's not protected against replays
+/// on other chains (EIP-155).
+fn is_unprotected_rejection(err: &web3::Error) -> bool {
+    let web3::Error::Rpc(err) = err else {
+        return false;
+    };
+    let message = err.message.to_lowercase();
+    message.contains("replay-protected") || message.contains("eip-155")
+}
+
+/// Whether the node doesn't implement the called method, e.g. `eth_feeHistory` before London.
+fn is_unsupported_method(err: &web3::Error) -> bool {
+    let web3::Error::Rpc(err) = err else {
+        return false;
+    };
+    let message = err.message.to_lowercase();
+    err.code.code() == -32601
+        || ["method not found", "not supported", "does not exist"]
+            .iter()
+            .any(|reason| message.contains(reason))
+}
+
+/// Whether sending the transaction has failed because a transaction with the same nonce
+/// is already mined or pending.
+fn is_superseded(err: &web3::Error) -> bool {
+    let web3::Error::Rpc(err) = err else {
+        return false;
+    };
+    let message = err.message.to_lowercase();
+    [
+        "nonce too low",
+        "already known",
+        "known transaction",
+        "replacement transaction underpriced",
+    ]
+    .iter()
+    .any(|reason| message.contains(reason))
+}
+
+/// ABI of the EIP-2470 singleton factory, used to decode the reverts of the factory calls.
+pub fn create2_factory_abi() -> ethabi::Contract {
+    let abi = serde_json::json!([{
+        "type": "function",
+        "name": "deploy",
+        "stateMutability": "nonpayable",
+        "inputs": [
+            { "name": "_initCode", "type": "bytes" },
+            { "name": "_salt", "type": "bytes32" }
+        ],
+        "outputs": [{ "name": "createdContract", "type": "address" }]
+    }]);
+    serde_json::from_value(abi).expect("Invalid singleton factory ABI")
+}
+
+/// Identifies the transaction regardless of its nonce and fees.
+fn intent_hash(from: Address, to: Option<Address>, data: &Bytes, value: U256) -> H256 {
+    let mut bytes = from.as_bytes().to_vec();
+    bytes.extend_from_slice(to.unwrap_or_default().as_bytes());
+    bytes.extend_from_slice(&<[u8; 32]>::from(value));
+    bytes.extend_from_slice(&data.0);
+    H256(keccak256(&bytes))
+}
+
+/// Transaction sent to L1 and not mined yet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PendingTransaction {
+    from: Address,
+    nonce: U256,
+    gas: U256,
+    /// Fees of the latest replacement.
+    fees: Fees,
+    intent: H256,
+    /// Hashes of the transaction and all of its replacements.
+    hashes: Vec<H256>,
+}
+
+/// Hands out nonces for the transactions and keeps track of the ones not mined yet.
+#[derive(Debug, Default)]
+struct NonceManager {
+    /// File the pending transactions are persisted to. In-memory only if not set.
+    path: Option<PathBuf>,
+    state: Mutex<NonceManagerState>,
+}
+
+#[derive(Debug, Default)]
+struct NonceManagerState {
+    next_nonces: HashMap<Address, U256>,
+    pending: Vec<PendingTransaction>,
+}
+
+impl NonceManager {
+    fn load(path: PathBuf) -> anyhow::Result<Self> {
+        let shell = xshell::Shell::new()?;
+        let pending: Vec<PendingTransaction> = if shell.path_exists(&path) {
+            serde_json::from_str(&shell.read_file(&path)?)?
+        } else {
+            Vec::new()
+        };
+        // Pending transactions may not have reached L1 yet, so their nonces are still taken.
+        let mut next_nonces = HashMap::new();
+        for tx in &pending {
+            let next_nonce: &mut U256 = next_nonces.entry(tx.from).or_default();
+            *next_nonce = (*next_nonce).max(tx.nonce + 1);
+        }
+        Ok(Self {
+            path: Some(path),
+            state: Mutex::new(NonceManagerState {
+                next_nonces,
+                pending,
+            }),
+        })
+    }
+
+    /// Next nonce for the sender given the one reported by L1. Takes into account the
+    /// transactions sent by this client that L1 may not be aware of yet.
+    fn next_nonce(&self, from: Address, l1_nonce: U256) -> U256 {
+        let state = self.state.lock().unwrap();
+        let next_nonce = state.next_nonces.get(&from).copied().unwrap_or_default();
+        l1_nonce.max(next_nonce)
+    }
+
+    fn pending(&self, intent: H256) -> Option<PendingTransaction> {
+        let state = self.state.lock().unwrap();
+        state.pending.iter().find(|tx| tx.intent == intent).cloned()
+    }
+
+    fn record(&self, tx: PendingTransaction) -> anyhow::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let next_nonce = state.next_nonces.entry(tx.from).or_default();
+        *next_nonce = (*next_nonce).max(tx.nonce + 1);
+        state.pending.retain(|pending| pending.intent != tx.intent);
+        state.pending.push(tx);
+        self.persist(&state.pending)
+    }
+
+    fn remove(&self, intent: H256) -> anyhow::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.pending.retain(|pending| pending.intent != intent);
+        self.persist(&state.pending)
+    }
+
+    /// Forgets the transaction that never reached L1, along with the nonce reserved for it.
+    fn release(&self, intent: H256) -> anyhow::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let Some(released) = state.pending.iter().find(|tx| tx.intent == intent).cloned() else {
+            return Ok(());
+        };
+        state.pending.retain(|pending| pending.intent != intent);
+        let next_nonce = state
+            .pending
+            .iter()
+            .filter(|pending| pending.from == released.from)
+            .map(|pending| pending.nonce + 1)
+            .max();
+        match next_nonce {
+            Some(next_nonce) => state.next_nonces.insert(released.from, next_nonce),
+            None => state.next_nonces.remove(&released.from),
+        };
+        self.persist(&state.pending)
+    }
+
+    fn persist(&self, pending: &[PendingTransaction]) -> anyhow::Result<()> {
+        if let Some(path) = &self.path {
+            xshell::Shell::new()?.write_file(path, serde_json::to_string_pretty(pending)?)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn l1_network_chain_ids() {
+        assert_eq!(L1Network::Localhost.chain_id(), 9);
+        assert_eq!(L1Network::Sepolia.chain_id(), 11_155_111);
+    }
+
+    #[test]
+    fn chain_id_matches_network() {
+        for network in [L1Network::Localhost, L1Network::Sepolia] {
+            check_chain_id("http://rpc", network.chain_id().into(), network).unwrap();
+        }
+    }
+
+    #[test]
+    fn superseded_transactions_are_not_fatal() {
+        let rpc_error = |message: &str| {
+            let error = serde_json::json!({ "code": -32000, "message": message });
+            web3::Error::Rpc(serde_json::from_value(error).unwrap())
+        };
+        assert!(is_superseded(&rpc_error("nonce too low")));
+        assert!(is_superseded(&rpc_error("already known")));
+        assert!(is_superseded(&rpc_error(
+            "replacement transaction underpriced"
+        )));
+        assert!(!is_superseded(&rpc_error("insufficient funds for gas")));
+        assert!(!is_superseded(&web3::Error::Unreachable));
+
+        // Error returned by geth without `--rpc.allow-unprotected-txs`.
+        let unprotected =
+            rpc_error("only replay-protected (EIP-155) transactions allowed over RPC");
+        assert!(is_unprotected_rejection(&unprotected));
+        assert!(!is_superseded(&unprotected));
+        assert!(!is_unprotected_rejection(&rpc_error("nonce too low")));
+    }
+
+    #[test]
+    fn only_unsupported_fee_history_falls_back_to_legacy() {
+        let method_not_found = serde_json::json!({
+            "code": -32601,
+            "message": "the method eth_feeHistory does not exist/is not available",
+        });
+        assert!(is_unsupported_method(&web3::Error::Rpc(
+            serde_json::from_value(method_not_found).unwrap()
+        )));
+        let rate_limited = serde_json::json!({ "code": -32005, "message": "rate limit exceeded" });
+        assert!(!is_unsupported_method(&web3::Error::Rpc(
+            serde_json::from_value(rate_limited).unwrap()
+        )));
+        assert!(!is_unsupported_method(&web3::Error::Unreachable));
+        assert!(!is_unsupported_method(&web3::Error::Transport(
+            web3::error::TransportError::Message("connection refused".to_string())
+        )));
+    }
+
+    #[test]
+    fn pending_transactions_reserve_nonces() {
+        let dir = xshell::Shell::new().unwrap().create_temp_dir().unwrap();
+        let path = dir.path().join(PENDING_TRANSACTIONS_FILE_NAME);
+        let from = Address::repeat_byte(1);
+        let manager = NonceManager::load(path.clone()).unwrap();
+        manager
+            .record(PendingTransaction {
+                from,
+                nonce: 5.into(),
+                gas: 21_000.into(),
+                fees: Fees::Legacy {
+                    gas_price: 1.into(),
+                },
+                intent: H256::repeat_byte(2),
+                hashes: vec![H256::repeat_byte(3)],
+            })
+            .unwrap();
+
+        let restarted = NonceManager::load(path).unwrap();
+        assert_eq!(restarted.next_nonce(from, 5.into()), 6.into());
+        assert_eq!(restarted.next_nonce(from, 7.into()), 7.into());
+        assert_eq!(
+            restarted.next_nonce(Address::repeat_byte(4), 1.into()),
+            1.into()
+        );
+    }
+
+    /// Serves JSON-RPC requests with the results returned by `respond` for the method names.
+    async fn mock_rpc(listener: tokio::net::TcpListener, respond: fn(&str) -> serde_json::Value) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let mut buf = Vec::new();
+                loop {
+                    let text = String::from_utf8_lossy(&buf).to_string();
+                    let request = text.split_once("\r\n\r\n").and_then(|(headers, body)| {
+                        let content_length: usize = headers.lines().find_map(|line| {
+                            let (name, value) = line.split_once(':')?;
+                            name.eq_ignore_ascii_case("content-length")
+                                .then(|| value.trim().parse().unwrap())
+                        })?;
+                        (body.len() >= content_length).then(|| {
+                            (
+                                headers.len() + 4 + content_length,
+                                body[..content_length].to_string(),
+                            )
+                        })
+                    });
+                    let Some((request_len, body)) = request else {
+                        let mut chunk = [0; 4096];
+                        match stream.read(&mut chunk).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(read) => buf.extend_from_slice(&chunk[..read]),
+                        }
+                        continue;
+                    };
+                    buf.drain(..request_len);
+                    let request: serde_json::Value = serde_json::from_str(&body).unwrap();
+                    let mut response = respond(request["method"].as_str().unwrap());
+                    response["jsonrpc"] = "2.0".into();
+                    response["id"] = request["id"].clone();
+                    let response = response.to_string();
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{response}",
+                        response.len()
+                    );
+                    stream.write_all(response.as_bytes()).await.unwrap();
+                }
+            });
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_send_releases_nonce() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(mock_rpc(listener, |method| match method {
+            "eth_chainId" => serde_json::json!({ "result": "0x9" }),
+            "eth_getTransactionCount" => serde_json::json!({ "result": "0x5" }),
+            "eth_sendRawTransaction" => serde_json::json!({
+                "error": { "code": -32000, "message": "insufficient funds for gas * price + value" },
+            }),
+            method => panic!("Unexpected call of {method}"),
+        }));
+
+        let dir = xshell::Shell::new().unwrap().create_temp_dir().unwrap();
+        let client = Web3Client::new(&url, L1Network::Localhost)
+            .await
+            .unwrap()
+            .for_hyperchain(dir.path())
+            .unwrap();
+        let signer = crate::signer::LocalSigner::new(H256::repeat_byte(1)).unwrap();
+        let l1_nonce = client.nonce(signer.address()).await.unwrap();
+        let tx = TransactionParameters {
+            nonce: Some(client.nonce_manager.next_nonce(signer.address(), l1_nonce)),
+            to: Some(Address::repeat_byte(2)),
+            gas_price: Some(1.into()),
+            chain_id: Some(client.chain_id()),
+            ..Default::default()
+        };
+        let err = client
+            .wait_or_replace(&signer, "Transfer", tx, Vec::new())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("insufficient funds"), "{err}");
+
+        let restarted =
+            NonceManager::load(dir.path().join(PENDING_TRANSACTIONS_FILE_NAME)).unwrap();
+        for manager in [&client.nonce_manager, &restarted] {
+            assert_eq!(manager.next_nonce(signer.address(), l1_nonce), l1_nonce);
+            assert!(manager.state.lock().unwrap().pending.is_empty());
+        }
+    }
+
+    #[test]
+    fn released_nonce_keeps_other_pending_transactions() {
+        let from = Address::repeat_byte(1);
+        let pending = |nonce: u64, intent: u8| PendingTransaction {
+            from,
+            nonce: nonce.into(),
+            gas: 21_000.into(),
+            fees: Fees::Legacy {
+                gas_price: 1.into(),
+            },
+            intent: H256::repeat_byte(intent),
+            hashes: vec![H256::repeat_byte(intent)],
+        };
+        let manager = NonceManager::default();
+        manager.record(pending(5, 1)).unwrap();
+        manager.record(pending(6, 2)).unwrap();
+        manager.release(H256::repeat_byte(2)).unwrap();
+        assert_eq!(manager.next_nonce(from, 0.into()), 6.into());
+        assert!(manager.pending(H256::repeat_byte(1)).is_some());
+        manager.release(H256::repeat_byte(1)).unwrap();
+        assert_eq!(manager.next_nonce(from, 0.into()), 0.into());
+    }
+
+    #[tokio::test]
+    async fn nonce_is_read_from_signed_transactions() {
+        let signer = crate::signer::LocalSigner::new(H256::repeat_byte(1)).unwrap();
+        let legacy = TransactionParameters {
+            nonce: Some(7.into()),
+            gas_price: Some(1.into()),
+            chain_id: Some(9),
+            ..Default::default()
+        };
+        let eip1559 = TransactionParameters {
+            nonce: Some(0x1234.into()),
+            max_fee_per_gas: Some(2.into()),
+            max_priority_fee_per_gas: Some(1.into()),
+            transaction_type: Some(2.into()),
+            chain_id: Some(11_155_111),
+            ..Default::default()
+        };
+        let raw = signer.sign_transaction(legacy).await.unwrap();
+        assert_eq!(raw_tx_nonce(&raw.0).unwrap(), 7.into());
+        let raw = signer.sign_transaction(eip1559).await.unwrap();
+        assert_eq!(raw_tx_nonce(&raw.0).unwrap(), 0x1234.into());
+    }
+
+    #[test]
+    fn create2_deployment_calls_factory_deploy() {
+        let contract =
+            ContractRepr::new(serde_json::json!({ "abi": [], "bytecode": "0x6001" }).to_string())
+                .unwrap();
+        let factory = Address::repeat_byte(1);
+        let salt = H256::repeat_byte(2);
+        let (address, data) = create2_deployment(factory, salt, &contract, &[]).unwrap();
+        assert_eq!(address, create2_address(factory, salt, &[0x60, 0x01]));
+
+        let deploy = create2_factory_abi().function("deploy").unwrap().clone();
+        assert_eq!(data.0[..4], deploy.short_signature());
+        let args = deploy.decode_input(&data.0[4..]).unwrap();
+        assert_eq!(
+            args,
+            [
+                ethabi::Token::Bytes(vec![0x60, 0x01]),
+                ethabi::Token::FixedBytes(salt.as_bytes().to_vec())
+            ]
+        );
+    }
+
+    #[test]
+    fn aggregate3_calls_round_trip() {
+        use ethabi::{ParamType, Token};
+
+        let target = Address::repeat_byte(0xaa);
+        let calls = vec![
+            (target, Bytes(vec![1, 2, 3, 4])),
+            (Address::repeat_byte(0xbb), Bytes(vec![5])),
+        ];
+        let data = encode_aggregate3(calls);
+        // Selector of `aggregate3((address,bool,bytes)[])`.
+        assert_eq!(data.0[..4], [0x82, 0xad, 0x56, 0xcb]);
+        let call_type =
+            ParamType::Tuple(vec![ParamType::Address, ParamType::Bool, ParamType::Bytes]);
+        let tokens =
+            ethabi::decode(&[ParamType::Array(Box::new(call_type))], &data.0[4..]).unwrap();
+        let Token::Array(decoded) = &tokens[0] else {
+            panic!("Unexpected calls {tokens:?}");
+        };
+        assert_eq!(
+            decoded[0],
+            Token::Tuple(vec![
+                Token::Address(target),
+                Token::Bool(true),
+                Token::Bytes(vec![1, 2, 3, 4])
+            ])
+        );
+        assert_eq!(decoded.len(), 2);
+
+        let output = ethabi::encode(&[Token::Array(vec![
+            Token::Tuple(vec![Token::Bool(true), Token::Bytes(vec![0x42; 32])]),
+            Token::Tuple(vec![Token::Bool(false), Token::Bytes(vec![0x08, 0xc3])]),
+        ])]);
+        let results = decode_aggregate3(&output, 2).unwrap();
+        assert_eq!(results, [Some(Bytes(vec![0x42; 32])), None]);
+        let err = decode_aggregate3(&output, 3).unwrap_err();
+        assert_eq!(err.to_string(), "Multicall3 returned 2 results for 3 calls");
+        assert!(decode_aggregate3(&[1, 2, 3], 1).is_err());
+    }
+
+    #[test]
+    fn chain_id_mismatch_is_reported() {
+        let err = check_chain_id("http://rpc", 1.into(), L1Network::Sepolia).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Web3 RPC with URL http://rpc serves chain 1, while Sepolia has chain ID 11155111"
+        );
     }
 }